@@ -0,0 +1,94 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+#ifndef STIC_H
+#define STIC_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Result of every call. `Ok` to `CorruptPayload` have the values the CLI exits with; the CLI
+// also exits with 4 for a truncated payload and with 130 when cancelled, which have values of
+// their own here.
+typedef enum {
+  STIC_STATUS_OK = 0,
+  STIC_STATUS_ERROR = 1,
+  STIC_STATUS_WRONG_PASSWORD = 2,
+  STIC_STATUS_CORRUPT_HEADER = 3,
+  STIC_STATUS_CORRUPT_PAYLOAD = 4,
+  STIC_STATUS_TRUNCATED_PAYLOAD = 5,
+  STIC_STATUS_CANCELLED = 6,
+  // A pointer was null or a string was not valid UTF-8.
+  STIC_STATUS_INVALID_ARGUMENT = 7,
+} SticStatus;
+
+// Description of the last failed call on this thread, or null if it succeeded. The string is
+// owned by the library and valid until the next call on this thread.
+const char *stic_last_error(void);
+
+// Encrypts the file or directory at `input_path` into `output_path`.
+//
+// # Safety
+//
+// The arguments must be null or point to NUL-terminated strings.
+SticStatus stic_encrypt_file(const char *input_path, const char *password, const char *output_path);
+
+// Decrypts the file at `input_path` into the existing directory `output_dir`, under the name it
+// was encrypted from.
+//
+// # Safety
+//
+// The arguments must be null or point to NUL-terminated strings.
+SticStatus stic_decrypt_file(const char *input_path, const char *password, const char *output_dir);
+
+// Encrypts `length` bytes at `data`. On success `*output` and `*output_length` hold the
+// ciphertext, to be released with `stic_buffer_free`.
+//
+// # Safety
+//
+// `data` must point to `length` readable bytes (or may be null if `length` is 0), `password`
+// to a NUL-terminated string, and `output` and `output_length` to writable locations.
+SticStatus stic_encrypt_buffer(const uint8_t *data,
+                               size_t length,
+                               const char *password,
+                               uint8_t **output,
+                               size_t *output_length);
+
+// Decrypts `length` bytes at `data`, the counterpart of `stic_encrypt_buffer`.
+//
+// # Safety
+//
+// Same as `stic_encrypt_buffer`.
+SticStatus stic_decrypt_buffer(const uint8_t *data,
+                               size_t length,
+                               const char *password,
+                               uint8_t **output,
+                               size_t *output_length);
+
+// Zeroes and releases a buffer returned by this library. Null is ignored.
+//
+// # Safety
+//
+// `buffer` and `length` must come from one successful call, and be freed only once.
+void stic_buffer_free(uint8_t *buffer, size_t length);
+
+#endif /* STIC_H */
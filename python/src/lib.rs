@@ -0,0 +1,335 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+//! Python bindings, built into the `stic` extension module with maturin (see `pyproject.toml`).
+
+use std::{
+    error::Error as StdError,
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+
+use stic::{Decryptor, Options, SticError};
+
+create_exception!(
+    stic,
+    Error,
+    PyException,
+    "Base class of the errors raised by stic."
+);
+create_exception!(
+    stic,
+    WrongPasswordError,
+    Error,
+    "The password does not open the file."
+);
+create_exception!(
+    stic,
+    CorruptHeaderError,
+    Error,
+    "The header is damaged; see `offset`."
+);
+create_exception!(
+    stic,
+    CorruptPayloadError,
+    Error,
+    "A segment failed authentication; see `offset`."
+);
+create_exception!(
+    stic,
+    TruncatedPayloadError,
+    Error,
+    "The file ends early; see `offset`."
+);
+create_exception!(stic, CancelledError, Error, "The operation was cancelled.");
+
+const READ_SIZE: usize = 1 << 20;
+
+/// Encrypts the file or directory at `input_path` into `output_path`.
+#[pyfunction]
+#[pyo3(signature = (input_path, password, output_path, *, cipher=None, compression=None, padding=None))]
+fn encrypt_file(
+    py: Python,
+    input_path: PathBuf,
+    password: &str,
+    output_path: PathBuf,
+    cipher: Option<&str>,
+    compression: Option<&str>,
+    padding: Option<&str>,
+) -> PyResult<()> {
+    let options = options(cipher, compression, padding)?;
+    py.allow_threads(|| {
+        stic::encrypt_file_with(&input_path, password, &output_path, &options)
+            .map_err(Failure::from)
+    })
+    .map_err(|e| e.into_py_err(py))
+}
+
+/// Decrypts the file at `input_path` into the existing directory `output_dir` and returns the
+/// path of what it restored.
+#[pyfunction]
+fn decrypt_file(
+    py: Python,
+    input_path: PathBuf,
+    password: &str,
+    output_dir: PathBuf,
+) -> PyResult<PathBuf> {
+    py.allow_threads(|| {
+        stic::decrypt_file_into(&input_path, password, &output_dir, &Options::new())
+            .map_err(Failure::from)
+    })
+    .map_err(|e| e.into_py_err(py))
+}
+
+/// Encrypts `data` into the bytes of an encrypted file.
+#[pyfunction]
+#[pyo3(signature = (data, password, *, cipher=None, compression=None, padding=None))]
+fn encrypt_bytes<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    password: &str,
+    cipher: Option<&str>,
+    compression: Option<&str>,
+    padding: Option<&str>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let options = options(cipher, compression, padding)?;
+    let ciphertext = py
+        .allow_threads(|| stic::encrypt_bytes(data, password, &options).map_err(Failure::from))
+        .map_err(|e| e.into_py_err(py))?;
+    Ok(PyBytes::new(py, &ciphertext))
+}
+
+/// Decrypts the bytes of an encrypted file.
+#[pyfunction]
+fn decrypt_bytes<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    password: &str,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let plaintext = py
+        .allow_threads(|| {
+            stic::decrypt_bytes(data, password, &Options::new()).map_err(Failure::from)
+        })
+        .map_err(|e| e.into_py_err(py))?;
+    Ok(PyBytes::new(py, &plaintext))
+}
+
+/// Read-only file object over the plaintext of an encrypted file, decrypted as it is read.
+///
+/// Files are encrypted as tar archives, so `tarfile.open(fileobj=reader, mode="r|")` lists and
+/// extracts them without a temporary copy.
+///
+/// Data is returned only once its segment has been authenticated, but a damaged or truncated
+/// file is only detected when the read reaches that point.
+#[pyclass(module = "stic")]
+struct DecryptReader {
+    file: Option<File>,
+    decryptor: Option<Decryptor>,
+    /// Plaintext decrypted but not yet read.
+    buffer: Vec<u8>,
+    closed: bool,
+}
+
+#[pymethods]
+impl DecryptReader {
+    #[new]
+    fn new(path: PathBuf, password: &str) -> PyResult<Self> {
+        let file = File::open(path)?;
+        Ok(DecryptReader {
+            file: Some(file),
+            decryptor: Some(Decryptor::new(password, &Options::new())),
+            buffer: Vec::new(),
+            closed: false,
+        })
+    }
+
+    /// Reads up to `size` bytes, or everything left if `size` is negative. Returns `b""` at
+    /// the end of the plaintext.
+    #[pyo3(signature = (size=-1))]
+    fn read<'py>(&mut self, py: Python<'py>, size: isize) -> PyResult<Bound<'py, PyBytes>> {
+        if self.closed {
+            return Err(PyValueError::new_err("I/O operation on closed file"));
+        }
+        let wanted = usize::try_from(size).unwrap_or(usize::MAX);
+        py.allow_threads(|| self.fill(wanted))
+            .map_err(|e| e.into_py_err(py))?;
+
+        let length = wanted.min(self.buffer.len());
+        let data = PyBytes::new(py, &self.buffer[..length]);
+        self.buffer.drain(..length);
+        Ok(data)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.closed
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.file = None;
+        self.decryptor = None;
+        self.buffer.clear();
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_args))]
+    fn __exit__(&mut self, _args: &Bound<PyAny>) {
+        self.close();
+    }
+}
+
+impl DecryptReader {
+    /// Decrypts until `buffer` holds `wanted` bytes or the file is exhausted.
+    fn fill(&mut self, wanted: usize) -> Result<(), Failure> {
+        let mut chunk = vec![0u8; READ_SIZE];
+        while self.buffer.len() < wanted {
+            let Some(decryptor) = &mut self.decryptor else {
+                break;
+            };
+            let file = self.file.as_mut().ok_or("I/O operation on closed file")?;
+
+            let read = file.read(&mut chunk)?;
+            if read == 0 {
+                let decryptor = self.decryptor.take().unwrap();
+                self.file = None;
+                self.buffer.extend_from_slice(&decryptor.finish()?);
+            } else {
+                self.buffer
+                    .extend_from_slice(&decryptor.update(&chunk[..read])?);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "stic")]
+fn stic_python(m: &Bound<PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_function(wrap_pyfunction!(encrypt_file, m)?)?;
+    m.add_function(wrap_pyfunction!(decrypt_file, m)?)?;
+    m.add_function(wrap_pyfunction!(encrypt_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(decrypt_bytes, m)?)?;
+    m.add_class::<DecryptReader>()?;
+    m.add("Error", py.get_type::<Error>())?;
+    m.add("WrongPasswordError", py.get_type::<WrongPasswordError>())?;
+    m.add("CorruptHeaderError", py.get_type::<CorruptHeaderError>())?;
+    m.add("CorruptPayloadError", py.get_type::<CorruptPayloadError>())?;
+    m.add(
+        "TruncatedPayloadError",
+        py.get_type::<TruncatedPayloadError>(),
+    )?;
+    m.add("CancelledError", py.get_type::<CancelledError>())?;
+    Ok(())
+}
+
+fn options(
+    cipher: Option<&str>,
+    compression: Option<&str>,
+    padding: Option<&str>,
+) -> PyResult<Options> {
+    let invalid = |e: Box<dyn StdError>| PyValueError::new_err(e.to_string());
+    let mut options = Options::new();
+    if let Some(cipher) = cipher {
+        options = options.algorithm(cipher.parse().map_err(invalid)?);
+    }
+    if let Some(compression) = compression {
+        options = options.compression(compression.parse().map_err(invalid)?);
+    }
+    if let Some(padding) = padding {
+        options = options.padding(padding.parse().map_err(invalid)?);
+    }
+    Ok(options)
+}
+
+/// An error carried out of `allow_threads`, where Python objects can't be built: `Box<dyn StdError>`
+/// is not `Send`, so only what the exception needs is kept.
+enum Failure {
+    Stic(SticError),
+    Io(io::Error),
+    Other(String),
+}
+
+impl From<Box<dyn StdError>> for Failure {
+    fn from(e: Box<dyn StdError>) -> Self {
+        match e.downcast::<SticError>() {
+            Ok(e) => Failure::Stic(*e),
+            Err(e) => match e.downcast::<io::Error>() {
+                Ok(e) => Failure::Io(*e),
+                Err(e) => Failure::Other(e.to_string()),
+            },
+        }
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Io(e)
+    }
+}
+
+impl From<&str> for Failure {
+    fn from(message: &str) -> Self {
+        Failure::Other(message.to_string())
+    }
+}
+
+impl Failure {
+    fn into_py_err(self, py: Python) -> PyErr {
+        let e = match self {
+            Failure::Stic(e) => e,
+            // Keeps the errno, so Python raises FileNotFoundError, PermissionError...
+            Failure::Io(e) => return PyErr::from(e),
+            Failure::Other(message) => return Error::new_err(message),
+        };
+
+        let message = e.to_string();
+        let (err, offset) = match e {
+            SticError::Cancelled => (CancelledError::new_err(message), None),
+            SticError::WrongPassword => (WrongPasswordError::new_err(message), None),
+            SticError::CorruptHeader { offset, .. } => {
+                (CorruptHeaderError::new_err(message), Some(offset))
+            }
+            SticError::CorruptPayload { offset } => {
+                (CorruptPayloadError::new_err(message), Some(offset))
+            }
+            SticError::TruncatedPayload { offset } => {
+                (TruncatedPayloadError::new_err(message), Some(offset))
+            }
+        };
+        if let Some(offset) = offset {
+            let _ = err.value(py).setattr("offset", offset);
+        }
+        err
+    }
+}
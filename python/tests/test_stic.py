@@ -0,0 +1,94 @@
+# This file is part of stic.
+#
+# stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+# General Public License as published by the Free Software Foundation, either version 3 of the
+# License, or (at your option) any later version.
+#
+# stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+# the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+# Public License for more details.
+#
+# You should have received a copy of the GNU General Public License along with stic.
+# If not, see <https://www.gnu.org/licenses/>.
+#
+# Copyright (C) 2022 Salvador Bravo Garnica
+
+import os
+import shutil
+import tarfile
+import tempfile
+import unittest
+
+import stic
+
+PASSWORD = "Passw0rd!"
+
+
+class BytesTest(unittest.TestCase):
+    def test_round_trip(self):
+        data = os.urandom(3 << 19)
+        encrypted = stic.encrypt_bytes(data, PASSWORD, compression="zstd")
+        self.assertEqual(stic.decrypt_bytes(encrypted, PASSWORD), data)
+
+    def test_wrong_password(self):
+        encrypted = stic.encrypt_bytes(b"data", PASSWORD)
+        with self.assertRaises(stic.WrongPasswordError):
+            stic.decrypt_bytes(encrypted, "Wr0ng password!")
+
+    def test_corrupt_payload(self):
+        encrypted = bytearray(stic.encrypt_bytes(b"data", PASSWORD))
+        encrypted[-1] ^= 1
+        with self.assertRaises(stic.CorruptPayloadError) as context:
+            stic.decrypt_bytes(bytes(encrypted), PASSWORD)
+        self.assertIsInstance(context.exception, stic.Error)
+        self.assertEqual(context.exception.offset, len(encrypted) - 20)
+
+    def test_unknown_cipher(self):
+        with self.assertRaises(ValueError):
+            stic.encrypt_bytes(b"data", PASSWORD, cipher="rot13")
+
+
+class FileTest(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.mkdtemp(prefix="stic-python-")
+        self.input = os.path.join(self.dir, "file.txt")
+        self.encrypted = self.input + ".ic"
+        self.data = os.urandom(5 << 19)
+        with open(self.input, "wb") as file:
+            file.write(self.data)
+        stic.encrypt_file(self.input, PASSWORD, self.encrypted)
+
+    def tearDown(self):
+        shutil.rmtree(self.dir)
+
+    def test_round_trip(self):
+        output_dir = os.path.join(self.dir, "out")
+        os.mkdir(output_dir)
+        restored = stic.decrypt_file(self.encrypted, PASSWORD, output_dir)
+        with open(restored, "rb") as file:
+            self.assertEqual(file.read(), self.data)
+
+    def test_missing_file(self):
+        with self.assertRaises(FileNotFoundError):
+            stic.encrypt_file(os.path.join(self.dir, "missing"), PASSWORD, self.encrypted + "2")
+
+    def test_reader(self):
+        with stic.DecryptReader(self.encrypted, PASSWORD) as reader:
+            with tarfile.open(fileobj=reader, mode="r|") as archive:
+                member = archive.next()
+                self.assertEqual(member.name, "file.txt")
+                self.assertEqual(archive.extractfile(member).read(), self.data)
+        self.assertTrue(reader.closed)
+        with self.assertRaises(ValueError):
+            reader.read()
+
+    def test_reader_truncated(self):
+        with open(self.encrypted, "r+b") as file:
+            file.truncate(os.path.getsize(self.encrypted) - 100)
+        with stic.DecryptReader(self.encrypted, PASSWORD) as reader:
+            with self.assertRaises(stic.Error):
+                reader.read()
+
+
+if __name__ == "__main__":
+    unittest.main()
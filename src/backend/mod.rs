@@ -0,0 +1,116 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+//! Cryptographic primitives behind the file format. Exactly one implementation is active,
+//! picked by the `backend-openssl` (default) or `backend-rustcrypto` feature; both produce the
+//! same bytes for the same inputs, so files move freely between builds.
+
+#[cfg(feature = "backend-openssl")]
+mod openssl;
+#[cfg(feature = "backend-rustcrypto")]
+#[cfg_attr(feature = "backend-openssl", allow(dead_code))]
+mod rustcrypto;
+
+use std::error::Error;
+
+pub(crate) type CipherResult = Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+
+#[cfg(not(any(feature = "backend-openssl", feature = "backend-rustcrypto")))]
+compile_error!("stic needs the backend-openssl or the backend-rustcrypto feature");
+
+pub(crate) trait Backend: Sync {
+    fn fill_random(&self, buffer: &mut [u8]) -> Result<(), Box<dyn Error>>;
+
+    fn sha3_512(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    fn hmac(&self, digest: Digest, key: &[u8], data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// PBKDF2 with HMAC-SHA3-512, filling the whole of `key`.
+    fn pbkdf2(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        iterations: usize,
+        key: &mut [u8],
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// AES-256-CBC with PKCS#7 padding, used to wrap the stream key.
+    fn aes_256_cbc_encrypt(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    fn aes_256_cbc_decrypt(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Returns `ciphertext || tag`.
+    fn aes_256_gcm_seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> CipherResult;
+
+    /// Reverses `aes_256_gcm_seal`, failing if the tag does not match.
+    fn aes_256_gcm_open(&self, key: &[u8], nonce: &[u8], aad: &[u8], sealed: &[u8])
+        -> CipherResult;
+
+    /// Incremental AES-256-GCM decryption for `VERSION_1` payloads, which are too large to hold
+    /// in memory. `iv` may have any length.
+    fn aes_256_gcm_decrypter(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+        aad: &[u8],
+    ) -> Result<Box<dyn Decrypter>, Box<dyn Error>>;
+
+    /// Compares secrets in constant time.
+    fn eq(&self, a: &[u8], b: &[u8]) -> bool;
+}
+
+/// An AES-256-GCM decryption in progress.
+pub(crate) trait Decrypter {
+    /// Decrypts `input` into `output`, which must hold `input.len() + BLOCK_SIZE` bytes, and
+    /// returns how many were written.
+    fn update(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, Box<dyn Error>>;
+
+    /// Fails if `tag` does not authenticate everything passed to `update`.
+    fn finish(self: Box<Self>, tag: &[u8]) -> Result<(), Box<dyn Error>>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Digest {
+    Sha3_256,
+    Sha3_512,
+}
+
+#[cfg(feature = "backend-openssl")]
+pub(crate) fn current() -> &'static dyn Backend {
+    &openssl::OpenSsl
+}
+
+#[cfg(all(feature = "backend-rustcrypto", not(feature = "backend-openssl")))]
+pub(crate) fn current() -> &'static dyn Backend {
+    &rustcrypto::RustCrypto
+}
@@ -0,0 +1,156 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::error::Error;
+
+use super::{Backend, CipherResult, Decrypter, Digest};
+use crate::constants;
+
+use openssl::{
+    hash::{hash, MessageDigest},
+    memcmp,
+    pkcs5::pbkdf2_hmac,
+    pkey::PKey,
+    rand::rand_bytes,
+    sign::Signer,
+    symm::{decrypt, decrypt_aead, encrypt, encrypt_aead, Cipher, Crypter, Mode},
+};
+
+pub struct OpenSsl;
+
+impl Backend for OpenSsl {
+    fn fill_random(&self, buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        Ok(rand_bytes(buffer)?)
+    }
+
+    fn sha3_512(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(hash(MessageDigest::sha3_512(), data)?.to_vec())
+    }
+
+    fn hmac(&self, digest: Digest, key: &[u8], data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let digest = match digest {
+            Digest::Sha3_256 => MessageDigest::sha3_256(),
+            Digest::Sha3_512 => MessageDigest::sha3_512(),
+        };
+        let key = PKey::hmac(key)?;
+        let mut signer = Signer::new(digest, &key)?;
+        signer.update(data)?;
+        Ok(signer.sign_to_vec()?)
+    }
+
+    fn pbkdf2(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        iterations: usize,
+        key: &mut [u8],
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(pbkdf2_hmac(
+            password,
+            salt,
+            iterations,
+            MessageDigest::sha3_512(),
+            key,
+        )?)
+    }
+
+    fn aes_256_cbc_encrypt(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(encrypt(Cipher::aes_256_cbc(), key, Some(iv), plaintext)?)
+    }
+
+    fn aes_256_cbc_decrypt(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(decrypt(Cipher::aes_256_cbc(), key, Some(iv), ciphertext)?)
+    }
+
+    fn aes_256_gcm_seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> CipherResult {
+        let mut tag = [0u8; constants::STREAM_TAG_SIZE];
+        let mut ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            key,
+            Some(nonce),
+            aad,
+            plaintext,
+            &mut tag,
+        )?;
+        ciphertext.extend_from_slice(&tag);
+        Ok(ciphertext)
+    }
+
+    fn aes_256_gcm_open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        sealed: &[u8],
+    ) -> CipherResult {
+        let (ciphertext, tag) = sealed.split_at(sealed.len() - constants::STREAM_TAG_SIZE);
+        Ok(decrypt_aead(
+            Cipher::aes_256_gcm(),
+            key,
+            Some(nonce),
+            aad,
+            ciphertext,
+            tag,
+        )?)
+    }
+
+    fn aes_256_gcm_decrypter(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+        aad: &[u8],
+    ) -> Result<Box<dyn Decrypter>, Box<dyn Error>> {
+        let mut crypter = Crypter::new(Cipher::aes_256_gcm(), Mode::Decrypt, key, Some(iv))?;
+        crypter.aad_update(aad)?;
+        Ok(Box::new(GcmDecrypter(crypter)))
+    }
+
+    fn eq(&self, a: &[u8], b: &[u8]) -> bool {
+        a.len() == b.len() && memcmp::eq(a, b)
+    }
+}
+
+struct GcmDecrypter(Crypter);
+
+impl Decrypter for GcmDecrypter {
+    fn update(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, Box<dyn Error>> {
+        Ok(self.0.update(input, output)?)
+    }
+
+    fn finish(mut self: Box<Self>, tag: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.0.set_tag(tag)?;
+        let mut finalize = [0u8; constants::BLOCK_SIZE];
+        self.0.finalize(&mut finalize)?;
+        Ok(())
+    }
+}
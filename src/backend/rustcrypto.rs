@@ -0,0 +1,248 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::error::Error;
+
+use super::{Backend, CipherResult, Decrypter, Digest};
+use crate::constants;
+
+use aes::{
+    cipher::{
+        block_padding::Pkcs7, BlockDecryptMut, BlockEncrypt, BlockEncryptMut, KeyInit, KeyIvInit,
+        StreamCipher,
+    },
+    Aes256,
+};
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm,
+};
+use ghash::{universal_hash::UniversalHash, GHash};
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use sha3::{Digest as _, Sha3_256, Sha3_512};
+use subtle::ConstantTimeEq;
+
+type Block = aes::Block;
+
+const GCM_BLOCK_SIZE: usize = 16;
+
+pub struct RustCrypto;
+
+impl Backend for RustCrypto {
+    fn fill_random(&self, buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        Ok(OsRng.try_fill_bytes(buffer)?)
+    }
+
+    fn sha3_512(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(Sha3_512::digest(data).to_vec())
+    }
+
+    fn hmac(&self, digest: Digest, key: &[u8], data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(match digest {
+            Digest::Sha3_256 => mac::<Hmac<Sha3_256>>(key, data)?,
+            Digest::Sha3_512 => mac::<Hmac<Sha3_512>>(key, data)?,
+        })
+    }
+
+    fn pbkdf2(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        iterations: usize,
+        key: &mut [u8],
+    ) -> Result<(), Box<dyn Error>> {
+        pbkdf2::pbkdf2_hmac::<Sha3_512>(password, salt, iterations.try_into()?, key);
+        Ok(())
+    }
+
+    fn aes_256_cbc_encrypt(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let cipher = cbc::Encryptor::<Aes256>::new_from_slices(key, iv)?;
+        Ok(cipher.encrypt_padded_vec_mut::<Pkcs7>(plaintext))
+    }
+
+    fn aes_256_cbc_decrypt(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let cipher = cbc::Decryptor::<Aes256>::new_from_slices(key, iv)?;
+        cipher
+            .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+            .map_err(|_| Box::from("Invalid padding in the wrapped key"))
+    }
+
+    fn aes_256_gcm_seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> CipherResult {
+        let cipher = Aes256Gcm::new_from_slice(key)?;
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        Ok(cipher.encrypt(nonce.into(), payload)?)
+    }
+
+    fn aes_256_gcm_open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        sealed: &[u8],
+    ) -> CipherResult {
+        let cipher = Aes256Gcm::new_from_slice(key)?;
+        let payload = Payload { msg: sealed, aad };
+        Ok(cipher.decrypt(nonce.into(), payload)?)
+    }
+
+    fn aes_256_gcm_decrypter(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+        aad: &[u8],
+    ) -> Result<Box<dyn Decrypter>, Box<dyn Error>> {
+        Ok(Box::new(GcmDecrypter::new(key, iv, aad)?))
+    }
+
+    fn eq(&self, a: &[u8], b: &[u8]) -> bool {
+        a.ct_eq(b).into()
+    }
+}
+
+fn mac<M: Mac + KeyInit>(key: &[u8], data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut mac = <M as Mac>::new_from_slice(key)?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// GCM built from its parts (NIST SP 800-38D): `aes-gcm` only works on whole messages and
+/// `VERSION_1` streams use a 32 byte IV.
+struct GcmDecrypter {
+    ctr: ctr::Ctr32BE<Aes256>,
+    ghash: GHash,
+    /// Ciphertext not yet hashed, less than a block.
+    pending: Vec<u8>,
+    aad_size: u64,
+    ciphertext_size: u64,
+    /// Encrypted initial counter block, masking the tag.
+    mask: Block,
+}
+
+impl GcmDecrypter {
+    fn new(key: &[u8], iv: &[u8], aad: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let aes = Aes256::new_from_slice(key)?;
+        let mut hash_key = Block::default();
+        aes.encrypt_block(&mut hash_key);
+
+        let mut counter = if iv.len() == constants::AES_256_GCM_NONCE_SIZE {
+            let mut counter = Block::default();
+            counter[..iv.len()].copy_from_slice(iv);
+            counter[GCM_BLOCK_SIZE - 1] = 1;
+            counter
+        } else {
+            let mut ghash = GHash::new(&hash_key);
+            ghash.update_padded(iv);
+            ghash.update(&[lengths(0, iv.len() as u64)]);
+            ghash.finalize()
+        };
+
+        let mut mask = counter;
+        aes.encrypt_block(&mut mask);
+
+        let next = u32::from_be_bytes(counter[12..].try_into().unwrap()).wrapping_add(1);
+        counter[12..].copy_from_slice(&next.to_be_bytes());
+
+        let mut ghash = GHash::new(&hash_key);
+        ghash.update_padded(aad);
+
+        Ok(GcmDecrypter {
+            ctr: ctr::Ctr32BE::<Aes256>::new(key.into(), &counter),
+            ghash,
+            pending: Vec::with_capacity(GCM_BLOCK_SIZE),
+            aad_size: aad.len() as u64,
+            ciphertext_size: 0,
+            mask,
+        })
+    }
+}
+
+impl GcmDecrypter {
+    /// Hashes whole blocks of ciphertext, holding back a partial one until more arrives.
+    fn authenticate(&mut self, mut input: &[u8]) {
+        self.ciphertext_size += input.len() as u64;
+
+        if !self.pending.is_empty() {
+            let taken = (GCM_BLOCK_SIZE - self.pending.len()).min(input.len());
+            self.pending.extend_from_slice(&input[..taken]);
+            input = &input[taken..];
+            if self.pending.len() < GCM_BLOCK_SIZE {
+                return;
+            }
+            self.ghash.update_padded(&self.pending);
+            self.pending.clear();
+        }
+
+        let whole = input.len() / GCM_BLOCK_SIZE * GCM_BLOCK_SIZE;
+        self.ghash.update_padded(&input[..whole]);
+        self.pending.extend_from_slice(&input[whole..]);
+    }
+}
+
+impl Decrypter for GcmDecrypter {
+    fn update(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, Box<dyn Error>> {
+        self.authenticate(input);
+        let output = &mut output[..input.len()];
+        output.copy_from_slice(input);
+        self.ctr.apply_keystream(output);
+        Ok(input.len())
+    }
+
+    fn finish(self: Box<Self>, tag: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut ghash = self.ghash;
+        ghash.update_padded(&self.pending);
+        ghash.update(&[lengths(self.aad_size, self.ciphertext_size)]);
+
+        let mut expected = ghash.finalize();
+        expected
+            .iter_mut()
+            .zip(self.mask.iter())
+            .for_each(|(byte, mask)| *byte ^= mask);
+
+        if !bool::from(expected.ct_eq(tag)) {
+            return Err(Box::from("Authentication tag mismatch"));
+        }
+        Ok(())
+    }
+}
+
+/// The final GHASH block, the sizes of the authenticated data and ciphertext in bits.
+fn lengths(aad_size: u64, ciphertext_size: u64) -> Block {
+    let mut block = Block::default();
+    block[..8].copy_from_slice(&(aad_size * 8).to_be_bytes());
+    block[8..].copy_from_slice(&(ciphertext_size * 8).to_be_bytes());
+    block
+}
@@ -15,14 +15,44 @@ If not, see <https://www.gnu.org/licenses/>.
 Copyright (C) 2022 Salvador Bravo Garnica
 */
 
+use std::error::Error;
+
 fn main() {
-    let config = stic::Config::parse().unwrap_or_else(|e| {
-        eprintln!("{e}");
-        std::process::exit(1);
-    });
+    // Keep keys and plaintext out of core dumps and away from ptrace by other processes of the
+    // same user
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0);
+    }
+
+    let config = stic::Config::parse().unwrap_or_else(|e| exit(&*e));
+
+    let cancel = stic::CancelToken::new();
+    #[cfg(not(target_family = "wasm"))]
+    {
+        let handler_cancel = cancel.clone();
+        if let Err(e) = ctrlc::set_handler(move || handler_cancel.cancel()) {
+            exit(&e);
+        }
+    }
+    let config = config.cancel(cancel);
 
     if let Err(e) = config.run() {
-        eprintln!("{e}");
-        std::process::exit(1);
+        exit(&*e);
     }
 }
+
+/// Reports `e` and exits with the status documented in the help for its kind.
+fn exit(e: &(dyn Error + 'static)) -> ! {
+    eprintln!("{e}");
+    let code = match e.downcast_ref() {
+        Some(stic::SticError::Cancelled) => 130,
+        Some(stic::SticError::WrongPassword) => 2,
+        Some(stic::SticError::CorruptHeader { .. }) => 3,
+        Some(stic::SticError::CorruptPayload { .. } | stic::SticError::TruncatedPayload { .. }) => {
+            4
+        }
+        None => 1,
+    };
+    std::process::exit(code);
+}
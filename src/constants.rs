@@ -17,20 +17,39 @@ Copyright (C) 2022 Salvador Bravo Garnica
 
 // Version
 pub const BUILD: &str = "0.1.0";
-pub const VERSION: &[u8] = b"\x00\x00\x00\x01";
+pub const VERSION: &[u8] = b"\x00\x00\x00\x04";
+pub const VERSION_3: &[u8] = b"\x00\x00\x00\x03";
+pub const VERSION_2: &[u8] = b"\x00\x00\x00\x02";
+pub const VERSION_1: &[u8] = b"\x00\x00\x00\x01";
 
 // Passwords
 pub const PASSWORD_MIN: usize = 8;
 pub const PASSWORD_MAX: usize = 4_000;
 pub const PBKDF2_HMAC_ITERATIONS: usize = 1_000_000;
+pub const KDF_PBKDF2_HMAC_SHA3_512: u8 = 1;
+pub const KDF_HMAC_SHA3_512: u8 = 2; // keyfiles, random enough not to need stretching
 pub const INVALID_PASSWORD_TIMEOUT: u64 = 2;
 
 // Files
 pub const EXTENSION: &str = "ic";
 pub const BUFFER_SIZE: usize = 10_485_760; // 10 MiB
-pub const MAX_FILE_SIZE: usize = 68_719_476_704; // ~64 GiB (see NIST 800-38D)
+pub const V1_MAX_FILE_SIZE: usize = 68_719_476_704; // ~64 GiB (see NIST 800-38D)
 pub const TMP_FILENAME_SIZE: u8 = 64;
 pub const TMP_FILENAME_CHARSET: &str = "0123456789abdef";
+pub const ANONYMIZED_NAME_SIZE: usize = 16; // random bytes, hex encoded
+
+// Armor
+pub const ARMOR_BEGIN: &str = "-----BEGIN STIC ENCRYPTED FILE-----";
+pub const ARMOR_END: &str = "-----END STIC ENCRYPTED FILE-----";
+pub const ARMOR_LINE_BYTES: usize = 48; // 64 base64 characters per line
+
+// Metadata
+pub const PAX_XATTR_PREFIX: &[u8] = b"SCHILY.xattr.";
+pub const UNPRESERVED_MODE_MASK: u32 = 0o7022; // setuid, setgid, sticky, group/other write
+
+// Progress
+pub const PROGRESS_BAR_WIDTH: usize = 30;
+pub const PROGRESS_REFRESH_MS: u64 = 100;
 
 // Tokens
 pub const TOKEN_KEY_SIZE: usize = 64;
@@ -41,12 +60,62 @@ pub const TOKEN_HMAC_SIZE: usize = 64;
 
 // Stream
 pub const BLOCK_SIZE: usize = 1;
-pub const STREAM_IV_SIZE: usize = 32;
+pub const STREAM_IV_SIZE: usize = 32; // VERSION_1 only
 pub const STREAM_TAG_SIZE: usize = 16;
 pub const STREAM_KEY_SIZE: usize = 32;
 
+// Algorithms
+pub const ALGORITHM_AES_256_GCM: u8 = 1;
+pub const ALGORITHM_XCHACHA20_POLY1305: u8 = 2;
+pub const AES_256_GCM_NONCE_SIZE: usize = 12;
+pub const XCHACHA20_POLY1305_NONCE_SIZE: usize = 24;
+
+// Compression
+pub const COMPRESSION_NONE: u8 = 0;
+pub const COMPRESSION_ZSTD: u8 = 1;
+pub const COMPRESSION_GZIP: u8 = 2;
+pub const COMPRESSION_XZ: u8 = 3;
+pub const ZSTD_DEFAULT_LEVEL: u32 = 3;
+pub const ZSTD_MAX_LEVEL: u32 = 22;
+pub const GZIP_XZ_DEFAULT_LEVEL: u32 = 6;
+pub const GZIP_XZ_MAX_LEVEL: u32 = 9;
+
+// Padding
+pub const PADDING_NONE: u8 = 0;
+pub const PADDING_PADME: u8 = 1;
+pub const PADDING_BUCKET: u8 = 2;
+pub const PADDING_LENGTH_SIZE: usize = 8;
+pub const PADDING_BUCKET_SIZE: u64 = 1_048_576; // 1 MiB
+
+// Segments
+pub const SEGMENT_SIZE: usize = 1_048_576; // 1 MiB
+pub const ENCRYPTED_SEGMENT_SIZE: usize = SEGMENT_SIZE + STREAM_TAG_SIZE;
+pub const SEGMENT_NONCE_SUFFIX_SIZE: usize = 9; // u64 counter + last segment flag
+pub const SEGMENTS_PER_THREAD: usize = 4;
+
 // Header
 pub const TOKEN_SIZE: usize =
-    VERSION.len() + TOKEN_SALT_SIZE + TOKEN_IV_SIZE + TOKEN_CIPHERTEXT_SIZE + TOKEN_HMAC_SIZE;
-pub const AAD_SIZE: usize = STREAM_IV_SIZE;
-pub const HEADER_SIZE: usize = TOKEN_SIZE + AAD_SIZE;
+    VERSION.len() + 1 + TOKEN_SALT_SIZE + TOKEN_IV_SIZE + TOKEN_CIPHERTEXT_SIZE + TOKEN_HMAC_SIZE;
+pub const KEY_COMMITMENT_SIZE: usize = 32; // VERSION_3 onwards
+pub const KEY_COMMITMENT_LABEL: &[u8] = b"stic key commitment";
+pub const MAX_HEADER_SIZE: usize = TOKEN_SIZE
+    + 3 // algorithm, compression and padding ids
+    + XCHACHA20_POLY1305_NONCE_SIZE
+    - SEGMENT_NONCE_SUFFIX_SIZE
+    + KEY_COMMITMENT_SIZE;
+
+// Benchmark
+pub const BENCH_SIZE: usize = 268_435_456; // 256 MiB
+
+// Git filter
+pub const KEYFILE_MIN_SIZE: usize = 32;
+pub const CONVERGENT_LABEL: &[u8] = b"stic convergent encryption";
+
+// Key cache
+pub const KEYRING_NAME: &str = "stic";
+pub const KEY_CACHE_TTL: u64 = 600; // seconds
+
+// Agent
+pub const AGENT_SOCKET_ENV: &str = "STIC_AGENT_SOCK";
+pub const AGENT_MAX_MESSAGE_SIZE: usize = 65_536;
+pub const AGENT_POLL_INTERVAL: u64 = 100; // milliseconds
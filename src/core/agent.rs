@@ -0,0 +1,373 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+//! `stic agent`: a daemon that holds unlocked identities, passwords or keyfiles, and opens
+//! tokens with them, so the password is entered once per session instead of once per file.
+//!
+//! Messages on the socket, requests and responses alike, are `tag (u8) || length (u32, big
+//! endian) || body`. Requests are tagged with an operation, responses with `OK` or `FAILED`,
+//! the body of a failure being its message.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    io::{self, Read, Write},
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt},
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    constants,
+    core::{decryption, Options},
+    format::Token,
+    utils::{self, SecureBuffer},
+};
+
+use zeroize::Zeroizing;
+
+const ADD: u8 = 1;
+const UNWRAP: u8 = 2;
+const LOCK: u8 = 3;
+const STATUS: u8 = 4;
+
+const OK: u8 = 0;
+const FAILED: u8 = 1;
+
+/// Where the agent listens: `$STIC_AGENT_SOCK`, or a socket in `$XDG_RUNTIME_DIR`, or else in a
+/// private directory of the temporary directory named after the user.
+pub fn agent_socket() -> PathBuf {
+    if let Some(path) = std::env::var_os(constants::AGENT_SOCKET_ENV) {
+        return PathBuf::from(path);
+    }
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join(format!("stic-agent-{}.sock", uid())),
+        None => std::env::temp_dir()
+            .join(format!("stic-{}", uid()))
+            .join("agent.sock"),
+    }
+}
+
+/// Serves requests on `socket` until `options` is cancelled, then removes the socket. Only the
+/// owner of the socket can connect to it.
+pub fn run_agent(socket: &Path, options: &Options) -> Result<(), Box<dyn Error>> {
+    let dir = socket.parent().ok_or("Path has no parent directory")?;
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    if !dir.exists() {
+        fs::DirBuilder::new().mode(0o700).create(dir)?;
+    }
+    check_directory(dir)?;
+
+    if UnixStream::connect(socket).is_ok() {
+        return Err(Box::from(format!(
+            "An agent is already listening on {}",
+            socket.display()
+        )));
+    }
+    let _ = fs::remove_file(socket);
+
+    let previous_umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(socket);
+    unsafe { libc::umask(previous_umask) };
+    let listener = listener?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+
+    let result = listen(&listener, options);
+    let _ = fs::remove_file(socket);
+    result
+}
+
+/// Accepts connections until `options` is cancelled, polling so that a signal is noticed even
+/// when nobody connects.
+fn listen(listener: &UnixListener, options: &Options) -> Result<(), Box<dyn Error>> {
+    listener.set_nonblocking(true)?;
+    let agent = Arc::new(Mutex::new(Agent::default()));
+    loop {
+        options.check()?;
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                let agent = Arc::clone(&agent);
+                let options = options.clone();
+                thread::spawn(move || serve(stream, &agent, &options));
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(constants::AGENT_POLL_INTERVAL));
+            }
+            Err(e) => return Err(Box::from(e)),
+        }
+    }
+}
+
+/// Hands `identity` to the agent, which forgets it after `timeout` if given.
+pub fn agent_add(
+    socket: &Path,
+    identity: &[u8],
+    timeout: Option<Duration>,
+) -> Result<(), Box<dyn Error>> {
+    let seconds = timeout.map_or(0, |timeout| timeout.as_secs().max(1));
+    let body = Zeroizing::new([&seconds.to_be_bytes(), identity].concat());
+    request(socket, ADD, &body)?;
+    Ok(())
+}
+
+/// Makes the agent forget every identity and every key it opened.
+pub fn agent_lock(socket: &Path) -> Result<(), Box<dyn Error>> {
+    request(socket, LOCK, &[])?;
+    Ok(())
+}
+
+/// Number of identities the agent holds, or `None` if no agent listens on `socket`.
+pub fn agent_identities(socket: &Path) -> Option<u32> {
+    let body = request(socket, STATUS, &[]).ok()?;
+    Some(u32::from_be_bytes(body[..].try_into().ok()?))
+}
+
+/// Asks the agent for the stream key in `token`, `None` if none of its identities opens it.
+pub(crate) fn agent_unwrap(
+    socket: &Path,
+    token: &Token,
+) -> Result<Option<SecureBuffer>, Box<dyn Error>> {
+    let key = request(socket, UNWRAP, &token.serialize())?;
+    Ok((!key.is_empty()).then_some(key))
+}
+
+#[derive(Default)]
+struct Agent {
+    identities: Vec<Entry>,
+    /// Stream keys already opened, by serialized token, so asking twice does not run the KDF
+    /// twice. The whole token is the key, HMAC included, so that a forged token reusing the salt
+    /// of an opened one is still checked.
+    keys: HashMap<Vec<u8>, Entry>,
+    /// Bumped by every lock, so that a key opened meanwhile is not kept.
+    generation: u64,
+}
+
+#[derive(Clone)]
+struct Entry {
+    secret: SecureBuffer,
+    expires: Option<Instant>,
+}
+
+impl Agent {
+    fn forget_expired(&mut self) {
+        let now = Instant::now();
+        let live = |entry: &Entry| entry.expires.is_none_or(|expires| expires > now);
+        self.identities.retain(live);
+        self.keys.retain(|_, entry| live(entry));
+    }
+
+    fn handle(&mut self, operation: u8, body: &[u8]) -> Result<SecureBuffer, Box<dyn Error>> {
+        match operation {
+            ADD if body.len() > 8 => {
+                let seconds = u64::from_be_bytes(body[..8].try_into().unwrap());
+                self.identities.push(Entry {
+                    secret: SecureBuffer::from(&body[8..]),
+                    expires: (seconds > 0).then(|| Instant::now() + Duration::from_secs(seconds)),
+                });
+                Ok(SecureBuffer::new(0))
+            }
+            LOCK => {
+                *self = Agent {
+                    generation: self.generation + 1,
+                    ..Agent::default()
+                };
+                Ok(SecureBuffer::new(0))
+            }
+            STATUS => Ok(SecureBuffer::from(
+                &(self.identities.len() as u32).to_be_bytes()[..],
+            )),
+            _ => Err(Box::from("Unknown agent request")),
+        }
+    }
+}
+
+/// Opens `token` with the identities of `agent`. The KDF runs on a copy of the identities with
+/// the agent unlocked, so that other clients, `lock` included, are not kept waiting.
+fn unwrap(
+    agent: &Mutex<Agent>,
+    token: &Token,
+    options: &Options,
+) -> Result<SecureBuffer, Box<dyn Error>> {
+    let serialized = token.serialize();
+    let (identities, generation) = {
+        let mut agent = agent.lock().unwrap();
+        agent.forget_expired();
+        if let Some(key) = agent.keys.get(&serialized) {
+            return Ok(key.secret.clone());
+        }
+        (agent.identities.clone(), agent.generation)
+    };
+
+    for identity in identities {
+        let password_key = utils::key_from_password(
+            options.get_registry().kdf(token.kdf),
+            &identity.secret,
+            &token.salt,
+        )?;
+        if let Some(key) = decryption::unwrap_stream_key(&password_key, token)? {
+            let mut agent = agent.lock().unwrap();
+            if agent.generation != generation {
+                break;
+            }
+            let entry = Entry {
+                secret: key.clone(),
+                expires: identity.expires,
+            };
+            agent.keys.insert(serialized, entry);
+            return Ok(key);
+        }
+    }
+    Ok(SecureBuffer::new(0))
+}
+
+fn serve(mut stream: UnixStream, agent: &Mutex<Agent>, options: &Options) {
+    if !matches!(peer_uid(&stream), Ok(peer) if peer == uid()) {
+        return;
+    }
+    while let Ok((operation, body)) = read_message(&mut stream) {
+        let result = match operation {
+            UNWRAP => Token::parse(&body).and_then(|token| unwrap(agent, &token, options)),
+            _ => {
+                let mut agent = agent.lock().unwrap();
+                agent.forget_expired();
+                agent.handle(operation, &body)
+            }
+        };
+
+        let written = match result {
+            Ok(body) => write_message(&mut stream, OK, &body),
+            Err(e) => write_message(&mut stream, FAILED, e.to_string().as_bytes()),
+        };
+        if written.is_err() {
+            return;
+        }
+    }
+}
+
+fn request(socket: &Path, operation: u8, body: &[u8]) -> Result<SecureBuffer, Box<dyn Error>> {
+    let mut stream = connect(socket)?;
+    write_message(&mut stream, operation, body)?;
+    let (status, body) = read_message(&mut stream)?;
+    if status != OK {
+        return Err(Box::from(String::from_utf8_lossy(&body).into_owned()));
+    }
+    Ok(body)
+}
+
+/// Connects to the agent on `socket`, provided both the socket and the process listening on it
+/// belong to this user.
+fn connect(socket: &Path) -> Result<UnixStream, Box<dyn Error>> {
+    let metadata = fs::symlink_metadata(socket)?;
+    if !metadata.file_type().is_socket()
+        || metadata.uid() != uid()
+        || metadata.mode() & 0o777 != 0o600
+    {
+        return Err(Box::from(format!(
+            "{} is not a private socket of this user",
+            socket.display()
+        )));
+    }
+    let stream = UnixStream::connect(socket)?;
+    if peer_uid(&stream)? != uid() {
+        return Err(Box::from(format!(
+            "The agent on {} runs as another user",
+            socket.display()
+        )));
+    }
+    Ok(stream)
+}
+
+/// Refuses a directory in which another user could swap the socket: one they own, or one they
+/// can write to without the sticky bit.
+fn check_directory(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let metadata = fs::metadata(dir)?;
+    let shared = metadata.mode() & 0o022 != 0 && metadata.mode() & 0o1000 == 0;
+    if (metadata.uid() != uid() && metadata.uid() != 0) || shared {
+        return Err(Box::from(format!(
+            "{} can be written by other users",
+            dir.display()
+        )));
+    }
+    Ok(())
+}
+
+fn uid() -> libc::uid_t {
+    unsafe { libc::getuid() }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut credentials: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut credentials as *mut libc::ucred).cast(),
+            &mut length,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(credentials.uid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let (mut uid, mut gid) = (0, 0);
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+fn write_message(stream: &mut UnixStream, tag: u8, body: &[u8]) -> io::Result<()> {
+    let mut message = Zeroizing::new(Vec::with_capacity(5 + body.len()));
+    message.push(tag);
+    message.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    message.extend_from_slice(body);
+    stream.write_all(&message)
+}
+
+fn read_message(stream: &mut UnixStream) -> io::Result<(u8, SecureBuffer)> {
+    let mut prefix = [0u8; 5];
+    stream.read_exact(&mut prefix)?;
+    let length = u32::from_be_bytes(prefix[1..].try_into().unwrap()) as usize;
+    if length > constants::AGENT_MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Agent message too large",
+        ));
+    }
+    let mut body = SecureBuffer::new(length);
+    stream.read_exact(&mut body)?;
+    Ok((prefix[0], body))
+}
@@ -0,0 +1,124 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{error::Error, fmt, str::FromStr};
+
+use crate::{
+    backend::{self, CipherResult},
+    constants,
+};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305,
+};
+
+/// Segment cipher, stored in the header by its `id`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    #[default]
+    Aes256Gcm,
+    XChaCha20Poly1305,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 2] = [Algorithm::Aes256Gcm, Algorithm::XChaCha20Poly1305];
+
+    pub fn id(self) -> u8 {
+        match self {
+            Algorithm::Aes256Gcm => constants::ALGORITHM_AES_256_GCM,
+            Algorithm::XChaCha20Poly1305 => constants::ALGORITHM_XCHACHA20_POLY1305,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, Box<dyn Error>> {
+        Algorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.id() == id)
+            .ok_or_else(|| Box::from(format!("Unknown algorithm id {id}")))
+    }
+
+    pub fn nonce_size(self) -> usize {
+        match self {
+            Algorithm::Aes256Gcm => constants::AES_256_GCM_NONCE_SIZE,
+            Algorithm::XChaCha20Poly1305 => constants::XCHACHA20_POLY1305_NONCE_SIZE,
+        }
+    }
+
+    /// Random part of the segment nonce stored in the header, the rest is the segment counter.
+    pub fn nonce_prefix_size(self) -> usize {
+        self.nonce_size() - constants::SEGMENT_NONCE_SUFFIX_SIZE
+    }
+
+    /// Returns `ciphertext || tag`.
+    pub(crate) fn seal(
+        self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> CipherResult {
+        match self {
+            Algorithm::Aes256Gcm => backend::current().aes_256_gcm_seal(key, nonce, aad, plaintext),
+            Algorithm::XChaCha20Poly1305 => {
+                let cipher = XChaCha20Poly1305::new_from_slice(key)?;
+                let payload = Payload {
+                    msg: plaintext,
+                    aad,
+                };
+                Ok(cipher.encrypt(nonce.into(), payload)?)
+            }
+        }
+    }
+
+    /// Reverses `seal`, failing if the tag does not match.
+    pub(crate) fn open(self, key: &[u8], nonce: &[u8], aad: &[u8], sealed: &[u8]) -> CipherResult {
+        if sealed.len() < constants::STREAM_TAG_SIZE {
+            return Err(Box::from("Encrypted file is truncated"));
+        }
+
+        match self {
+            Algorithm::Aes256Gcm => backend::current().aes_256_gcm_open(key, nonce, aad, sealed),
+            Algorithm::XChaCha20Poly1305 => {
+                let cipher = XChaCha20Poly1305::new_from_slice(key)?;
+                let payload = Payload { msg: sealed, aad };
+                Ok(cipher.decrypt(nonce.into(), payload)?)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Algorithm::Aes256Gcm => "aes-256-gcm",
+            Algorithm::XChaCha20Poly1305 => "xchacha20-poly1305",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = Box<dyn Error>;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Algorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.to_string() == name)
+            .ok_or_else(|| Box::from(format!("Unknown algorithm {name}")))
+    }
+}
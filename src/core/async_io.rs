@@ -0,0 +1,250 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+//! Async counterparts of the file functions and `Encryptor`/`Decryptor` for tokio. The
+//! password KDF and the file functions run on tokio's blocking pool, while the adapters seal and
+//! open one segment at a time on the calling task.
+
+use std::{
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use crate::{
+    constants,
+    core::{
+        decrypt_file_into, decrypt_file_with, decryption, encrypt_file_with, encryption, Decryptor,
+        Encryptor, Options,
+    },
+    error::SticError,
+    format::Header,
+    utils::SecureBuffer,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf},
+    task,
+};
+use zeroize::Zeroizing;
+
+pub async fn encrypt_file_async(
+    input_path: &Path,
+    password: &str,
+    output_path: &Path,
+    options: &Options,
+) -> io::Result<()> {
+    let (input_path, output_path) = (input_path.to_path_buf(), output_path.to_path_buf());
+    let password = Zeroizing::new(password.to_string());
+    let options = options.clone();
+    blocking(move || encrypt_file_with(&input_path, &password, &output_path, &options)).await
+}
+
+pub async fn decrypt_file_async(
+    input_path: &Path,
+    password: &str,
+    output_path: &Path,
+    options: &Options,
+) -> io::Result<()> {
+    let (input_path, output_path) = (input_path.to_path_buf(), output_path.to_path_buf());
+    let password = Zeroizing::new(password.to_string());
+    let options = options.clone();
+    blocking(move || decrypt_file_with(&input_path, &password, &output_path, &options)).await
+}
+
+pub async fn decrypt_file_into_async(
+    input_path: &Path,
+    password: &str,
+    output_dir: &Path,
+    options: &Options,
+) -> io::Result<PathBuf> {
+    let (input_path, output_dir) = (input_path.to_path_buf(), output_dir.to_path_buf());
+    let password = Zeroizing::new(password.to_string());
+    let options = options.clone();
+    blocking(move || decrypt_file_into(&input_path, &password, &output_dir, &options)).await
+}
+
+/// Encrypts everything written to it into `inner`, like `Encryptor`. `shutdown` writes the last
+/// segment, so it has to be called for the output to be complete.
+pub struct AsyncEncryptWriter<W> {
+    inner: W,
+    encryptor: Option<Encryptor>,
+    /// Ciphertext not yet accepted by `inner`.
+    pending: Vec<u8>,
+    written: usize,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncEncryptWriter<W> {
+    pub async fn new(inner: W, password: &str, options: &Options) -> io::Result<Self> {
+        let password = SecureBuffer::from(password.as_bytes());
+        let kdf_options = options.clone();
+        let (key, token) =
+            blocking(move || encryption::new_stream_key(&password, &kdf_options)).await?;
+
+        Ok(AsyncEncryptWriter {
+            inner,
+            encryptor: Some(Encryptor::with_key(key, token, options).map_err(io_error)?),
+            pending: Vec::new(),
+            written: 0,
+        })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn poll_pending(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        while self.written < self.pending.len() {
+            let count =
+                ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.written..]))?;
+            if count == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += count;
+        }
+        self.pending.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncEncryptWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+
+        let Some(encryptor) = &mut this.encryptor else {
+            return Poll::Ready(Err(io::Error::other("Write after shutdown")));
+        };
+        this.pending = encryptor.update(buf).map_err(io_error)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(encryptor) = this.encryptor.take() {
+            let last = encryptor.finish().map_err(io_error)?;
+            this.pending.extend_from_slice(&last);
+        }
+        ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Decrypts what `inner` yields, like `Decryptor`. The end of the plaintext is only reported
+/// once the last segment authenticated.
+pub struct AsyncDecryptReader<R> {
+    inner: R,
+    decryptor: Option<Decryptor>,
+    buffer: Vec<u8>,
+    /// Plaintext not yet handed out.
+    plaintext: Zeroizing<Vec<u8>>,
+    position: usize,
+}
+
+impl<R: AsyncRead + Unpin> AsyncDecryptReader<R> {
+    /// Reads the header from `inner` and derives the keys.
+    pub async fn new(mut inner: R, password: &str, options: &Options) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(constants::MAX_HEADER_SIZE);
+        (&mut inner)
+            .take(constants::MAX_HEADER_SIZE as u64)
+            .read_to_end(&mut header)
+            .await?;
+        let parsed = Header::parse(&header).map_err(io_error)?;
+
+        let password = SecureBuffer::from(password.as_bytes());
+        let (token, kdf_options) = (parsed.token.clone(), options.clone());
+        let key =
+            blocking(move || decryption::get_stream_key(&password, &token, &kdf_options)).await?;
+
+        let mut decryptor = Decryptor::with_key(&parsed, key, options).map_err(io_error)?;
+        let plaintext = decryptor
+            .update(&header[parsed.size()..])
+            .map_err(io_error)?;
+
+        Ok(AsyncDecryptReader {
+            inner,
+            decryptor: Some(decryptor),
+            buffer: vec![0u8; constants::ENCRYPTED_SEGMENT_SIZE],
+            plaintext: Zeroizing::new(plaintext),
+            position: 0,
+        })
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncDecryptReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.position < this.plaintext.len() {
+                let count = (this.plaintext.len() - this.position).min(buf.remaining());
+                buf.put_slice(&this.plaintext[this.position..this.position + count]);
+                this.position += count;
+                return Poll::Ready(Ok(()));
+            }
+            if this.decryptor.is_none() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = ReadBuf::new(&mut this.buffer);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+            let plaintext = if chunk.filled().is_empty() {
+                this.decryptor.take().unwrap().finish()
+            } else {
+                this.decryptor.as_mut().unwrap().update(chunk.filled())
+            };
+
+            this.plaintext = Zeroizing::new(plaintext.map_err(io_error)?);
+            this.position = 0;
+        }
+    }
+}
+
+async fn blocking<T, F>(work: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Box<dyn Error>> + Send + 'static,
+{
+    task::spawn_blocking(move || work().map_err(io_error))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// `Box<dyn Error>` can't cross threads or `.await`s, so errors are carried as `io::Error`s,
+/// with `SticError`s still reachable through `get_ref`.
+fn io_error(error: Box<dyn Error>) -> io::Error {
+    let error = match error.downcast::<SticError>() {
+        Ok(error) => return io::Error::other(*error),
+        Err(error) => error,
+    };
+    match error.downcast::<io::Error>() {
+        Ok(error) => *error,
+        Err(error) => io::Error::other(error.to_string()),
+    }
+}
@@ -0,0 +1,102 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{error::Error, io::sink, time::Instant};
+
+use crate::{
+    backend, constants,
+    core::{stream, stream::SegmentCipher, Algorithm, Options},
+};
+
+pub struct BenchResult {
+    pub algorithm: Algorithm,
+    pub threads: usize,
+    /// Bytes per second.
+    pub encrypt: f64,
+    /// Bytes per second.
+    pub decrypt: f64,
+}
+
+/// Measures segment encryption and decryption throughput of every algorithm over `BENCH_SIZE`
+/// bytes of random data in memory, doubling the thread count from one up to `options`' thread
+/// count.
+pub fn bench(options: &Options) -> Result<Vec<BenchResult>, Box<dyn Error>> {
+    let mut plaintext = vec![0u8; constants::BENCH_SIZE];
+    backend::current().fill_random(&mut plaintext)?;
+
+    let mut key = [0u8; constants::STREAM_KEY_SIZE];
+    backend::current().fill_random(&mut key)?;
+
+    let max_threads = options.thread_count();
+    let mut thread_counts = vec![];
+    let mut threads = 1;
+    while threads < max_threads {
+        thread_counts.push(threads);
+        threads *= 2;
+    }
+    thread_counts.push(max_threads);
+
+    let mut results = vec![];
+    for algorithm in Algorithm::ALL {
+        let mut nonce_prefix = vec![0u8; algorithm.nonce_prefix_size()];
+        backend::current().fill_random(&mut nonce_prefix)?;
+
+        let aad = [constants::VERSION, &[algorithm.id()], &nonce_prefix].concat();
+        let cipher = SegmentCipher {
+            aead: options.get_registry().aead(algorithm),
+            key: &key,
+            nonce_prefix: &nonce_prefix,
+            aad: &aad,
+            payload_offset: 0,
+        };
+
+        for &threads in &thread_counts {
+            options.check()?;
+            let options = options.clone().threads(threads);
+
+            let mut ciphertext = Vec::with_capacity(constants::BENCH_SIZE);
+            let start = Instant::now();
+            stream::encrypt_segments(
+                &mut plaintext.as_slice(),
+                &mut ciphertext,
+                plaintext.len() as u64,
+                &cipher,
+                &options,
+            )?;
+            let encrypt = plaintext.len() as f64 / start.elapsed().as_secs_f64();
+
+            let start = Instant::now();
+            stream::decrypt_segments(
+                &mut ciphertext.as_slice(),
+                &mut sink(),
+                ciphertext.len() as u64,
+                &cipher,
+                &options,
+            )?;
+            let decrypt = plaintext.len() as f64 / start.elapsed().as_secs_f64();
+
+            results.push(BenchResult {
+                algorithm,
+                threads,
+                encrypt,
+                decrypt,
+            });
+        }
+    }
+
+    Ok(results)
+}
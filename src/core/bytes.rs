@@ -0,0 +1,424 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    error::Error,
+    io::{Read, Write},
+    mem,
+};
+
+use crate::{
+    backend, constants,
+    core::{decryption, encryption, stream, Algorithm, Options},
+    error::SticError,
+    format::{Header, Token, Version},
+    utils::{Decoder, Encoder, Padding, SecureBuffer, Unpadder},
+};
+
+use zeroize::Zeroizing;
+
+/// Encrypts `plaintext` in memory. The payload is the data itself rather than a tar archive, so
+/// the result is read back with `decrypt_bytes` or a `Decryptor`, not `decrypt_file`.
+pub fn encrypt_bytes(
+    plaintext: &[u8],
+    password: &str,
+    options: &Options,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut encryptor = Encryptor::new(password, options)?;
+    let mut ciphertext = encryptor.update(plaintext)?;
+    ciphertext.extend_from_slice(&encryptor.finish()?);
+    Ok(ciphertext)
+}
+
+/// Reverses `encrypt_bytes`.
+pub fn decrypt_bytes(
+    ciphertext: &[u8],
+    password: &str,
+    options: &Options,
+) -> Result<Zeroizing<Vec<u8>>, Box<dyn Error>> {
+    let mut decryptor = Decryptor::new(password, options);
+    let mut plaintext = Zeroizing::new(decryptor.update(ciphertext)?);
+    plaintext.extend_from_slice(&decryptor.finish()?);
+    Ok(plaintext)
+}
+
+/// Encrypts everything `reader` yields into `writer`, like `encrypt_bytes` without holding the
+/// data in memory.
+pub fn encrypt_stream<R: Read, W: Write>(
+    reader: &mut R,
+    password: &str,
+    writer: &mut W,
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let encryptor = Encryptor::new(password, options)?;
+    encryptor.copy(reader, writer)
+}
+
+/// Reverses `encrypt_stream`. Everything written before an error was authenticated, but a
+/// truncated or tampered stream is only detected once it gets there.
+pub fn decrypt_stream<R: Read, W: Write>(
+    reader: &mut R,
+    password: &str,
+    writer: &mut W,
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let decryptor = Decryptor::new(password, options);
+    decryptor.copy(reader, writer)
+}
+
+/// Incremental encryption of data pushed in pieces, for callers that own the I/O.
+///
+/// The length is unknown up front, so padding is refused and the header never records it.
+/// Armoring is left to the caller.
+pub struct Encryptor {
+    segments: Segments,
+    compressor: Encoder<Vec<u8>>,
+    /// The serialized header, handed out with the first ciphertext.
+    header: Vec<u8>,
+}
+
+impl Encryptor {
+    /// Derives the keys, which takes as long as the password KDF.
+    pub fn new(password: &str, options: &Options) -> Result<Self, Box<dyn Error>> {
+        let (key, token) = encryption::new_stream_key(password.as_bytes(), options)?;
+        Encryptor::with_key(key, token, options)
+    }
+
+    pub(crate) fn with_key(
+        key: SecureBuffer,
+        token: Token,
+        options: &Options,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut nonce_prefix = vec![0u8; options.get_algorithm().nonce_prefix_size()];
+        backend::current().fill_random(&mut nonce_prefix)?;
+        Encryptor::with_nonce_prefix(key, token, nonce_prefix, options)
+    }
+
+    /// Like `with_key`, for callers that pick the nonce prefix themselves. It must never repeat
+    /// under the same key.
+    pub(crate) fn with_nonce_prefix(
+        key: SecureBuffer,
+        token: Token,
+        nonce_prefix: Vec<u8>,
+        options: &Options,
+    ) -> Result<Self, Box<dyn Error>> {
+        if options.get_padding() != Padding::None {
+            return Err(Box::from(
+                "Padding needs the size of the input, which is unknown when streaming",
+            ));
+        }
+
+        let header = Header {
+            token,
+            algorithm: options.get_algorithm(),
+            compression: options.get_compression(),
+            padding: Padding::None,
+            nonce: nonce_prefix,
+            commitment: Some(stream::key_commitment(&key)?),
+        };
+        let compressor = header
+            .compression
+            .encoder(Vec::new(), options.get_compression_level())?;
+
+        Ok(Encryptor {
+            segments: Segments::new(&header, key, options),
+            compressor,
+            header: header.serialize(),
+        })
+    }
+
+    /// Encrypts `plaintext`, returning the ciphertext completed so far, possibly none.
+    pub fn update(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.segments.options.check()?;
+        self.compressor.write_all(plaintext)?;
+        let compressed = Zeroizing::new(mem::take(self.compressor.get_mut()));
+
+        let mut ciphertext = mem::take(&mut self.header);
+        self.segments.seal(&compressed, &mut ciphertext)?;
+        Ok(ciphertext)
+    }
+
+    /// Returns the rest of the ciphertext, ending with the last segment.
+    pub fn finish(mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let compressed = Zeroizing::new(self.compressor.finish()?);
+
+        let mut ciphertext = self.header;
+        self.segments.seal(&compressed, &mut ciphertext)?;
+        self.segments.seal_last(&mut ciphertext)?;
+        Ok(ciphertext)
+    }
+
+    /// Encrypts everything `reader` yields into `writer`.
+    pub(crate) fn copy<R: Read, W: Write>(
+        mut self,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut buffer = SecureBuffer::new(constants::SEGMENT_SIZE);
+        loop {
+            let count = reader.read(&mut buffer)?;
+            if count == 0 {
+                break;
+            }
+            writer.write_all(&self.update(&buffer[..count])?)?;
+        }
+        writer.write_all(&self.finish()?)?;
+        Ok(())
+    }
+}
+
+/// Incremental decryption of data pushed in pieces, the counterpart of `Encryptor`.
+///
+/// The keys are derived once the header is complete, so the `update` call that completes it
+/// takes as long as the password KDF.
+pub struct Decryptor {
+    password: SecureBuffer,
+    options: Options,
+    /// Input received before the header was complete.
+    header: Vec<u8>,
+    opened: Option<Opened>,
+}
+
+struct Opened {
+    segments: Segments,
+    payload: Unpadder<Decoder<Vec<u8>>>,
+}
+
+impl Decryptor {
+    pub fn new(password: &str, options: &Options) -> Self {
+        Decryptor::with_password(password.as_bytes(), options)
+    }
+
+    /// Like `new`, for key material that is not text, such as a keyfile.
+    pub(crate) fn with_password(password: &[u8], options: &Options) -> Self {
+        Decryptor {
+            password: SecureBuffer::from(password),
+            options: options.clone(),
+            header: Vec::with_capacity(constants::MAX_HEADER_SIZE),
+            opened: None,
+        }
+    }
+
+    /// Starts after the header, whose keys were derived by the caller.
+    #[cfg(feature = "async")]
+    pub(crate) fn with_key(
+        header: &Header,
+        key: SecureBuffer,
+        options: &Options,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut decryptor = Decryptor::new("", options);
+        decryptor.open(header, key)?;
+        Ok(decryptor)
+    }
+
+    /// Decrypts `ciphertext`, returning the plaintext authenticated so far, possibly none.
+    pub fn update(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.options.check()?;
+
+        let Some(opened) = &mut self.opened else {
+            self.header.extend_from_slice(ciphertext);
+            if self.header.len() < constants::MAX_HEADER_SIZE {
+                return Ok(Vec::new());
+            }
+            let payload = self.read_header()?;
+            return self.update(&payload);
+        };
+
+        let mut plaintext = Zeroizing::new(Vec::new());
+        opened.segments.open(ciphertext, &mut plaintext)?;
+        opened.payload.write_all(&plaintext)?;
+        Ok(mem::take(opened.payload.get_mut().get_mut()))
+    }
+
+    /// Returns the rest of the plaintext, failing if the ciphertext ended early.
+    pub fn finish(mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut plaintext = Vec::new();
+        if self.opened.is_none() {
+            let payload = self.read_header()?;
+            plaintext = self.update(&payload)?;
+        }
+        let mut opened = self.opened.unwrap();
+
+        let mut last = Zeroizing::new(Vec::new());
+        opened.segments.open_last(&mut last)?;
+        opened.payload.write_all(&last)?;
+
+        let decompressed = opened.payload.finish()?.finish()?;
+        plaintext.extend_from_slice(&decompressed);
+        Ok(plaintext)
+    }
+
+    /// Decrypts everything `reader` yields into `writer`.
+    pub(crate) fn copy<R: Read, W: Write>(
+        mut self,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut buffer = vec![0u8; constants::ENCRYPTED_SEGMENT_SIZE];
+        loop {
+            let count = reader.read(&mut buffer)?;
+            if count == 0 {
+                break;
+            }
+            writer.write_all(&self.update(&buffer[..count])?)?;
+        }
+        writer.write_all(&self.finish()?)?;
+        Ok(())
+    }
+
+    /// Parses the buffered header and derives the keys, returning the payload bytes after it.
+    fn read_header(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let header = Header::parse(&self.header)?;
+        let key = decryption::get_stream_key(&self.password, &header.token, &self.options)?;
+        self.open(&header, key)?;
+        Ok(self.header.split_off(header.size()))
+    }
+
+    fn open(&mut self, header: &Header, key: SecureBuffer) -> Result<(), Box<dyn Error>> {
+        if header.version() == Version::V1 {
+            return Err(Box::from(
+                "Version 1 files hold a single stream, decrypt them with decrypt_file",
+            ));
+        }
+        decryption::check_commitment(header, &key)?;
+
+        let decoder = header.compression.write_decoder(Vec::new())?;
+        self.opened = Some(Opened {
+            segments: Segments::new(header, key, &self.options),
+            payload: header.padding.unpad(decoder),
+        });
+        Ok(())
+    }
+}
+
+/// Seals or opens the segments of a payload whose length is unknown up front, holding back
+/// input until it is known not to be the last segment. Unlike `stream`, single threaded.
+struct Segments {
+    options: Options,
+    algorithm: Algorithm,
+    key: SecureBuffer,
+    nonce_prefix: Vec<u8>,
+    aad: Vec<u8>,
+    index: u64,
+    /// Position of the next segment in the file, for error reports.
+    offset: u64,
+    pending: Zeroizing<Vec<u8>>,
+    /// Bytes of `pending` already sealed or opened, dropped once per call rather than once per
+    /// segment, so that a large input is not moved again for every segment.
+    consumed: usize,
+}
+
+impl Segments {
+    fn new(header: &Header, key: SecureBuffer, options: &Options) -> Self {
+        Segments {
+            options: options.clone(),
+            algorithm: header.algorithm,
+            key,
+            nonce_prefix: header.nonce.clone(),
+            aad: header.aad(),
+            index: 0,
+            offset: header.size() as u64,
+            pending: Zeroizing::new(Vec::new()),
+            consumed: 0,
+        }
+    }
+
+    fn seal(&mut self, plaintext: &[u8], ciphertext: &mut Vec<u8>) -> Result<(), Box<dyn Error>> {
+        self.pending.extend_from_slice(plaintext);
+        while self.pending.len() - self.consumed > constants::SEGMENT_SIZE {
+            self.seal_segment(constants::SEGMENT_SIZE, false, ciphertext)?;
+        }
+        self.compact();
+        Ok(())
+    }
+
+    fn seal_last(&mut self, ciphertext: &mut Vec<u8>) -> Result<(), Box<dyn Error>> {
+        self.seal_segment(self.pending.len() - self.consumed, true, ciphertext)
+    }
+
+    fn seal_segment(
+        &mut self,
+        size: usize,
+        last: bool,
+        ciphertext: &mut Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        let nonce = stream::segment_nonce(&self.nonce_prefix, self.index, last);
+        let aead = self.options.get_registry().aead(self.algorithm);
+        let segment = &self.pending[self.consumed..][..size];
+        let sealed = aead
+            .seal(&self.key, &nonce, &self.aad, segment)
+            .map_err(|e| e as Box<dyn Error>)?;
+        ciphertext.extend_from_slice(&sealed);
+
+        self.consumed += size;
+        self.index += 1;
+        Ok(())
+    }
+
+    fn open(&mut self, ciphertext: &[u8], plaintext: &mut Vec<u8>) -> Result<(), Box<dyn Error>> {
+        self.pending.extend_from_slice(ciphertext);
+        while self.pending.len() - self.consumed > constants::ENCRYPTED_SEGMENT_SIZE {
+            self.open_segment(constants::ENCRYPTED_SEGMENT_SIZE, false, plaintext)?;
+        }
+        self.compact();
+        Ok(())
+    }
+
+    fn open_last(&mut self, plaintext: &mut Vec<u8>) -> Result<(), Box<dyn Error>> {
+        self.open_segment(self.pending.len() - self.consumed, true, plaintext)
+    }
+
+    fn open_segment(
+        &mut self,
+        size: usize,
+        last: bool,
+        plaintext: &mut Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        let aead = self.options.get_registry().aead(self.algorithm);
+        let segment = &self.pending[self.consumed..][..size];
+        let open = |last| {
+            let nonce = stream::segment_nonce(&self.nonce_prefix, self.index, last);
+            aead.open(&self.key, &nonce, &self.aad, segment)
+        };
+
+        let opened = match open(last) {
+            Ok(opened) => Zeroizing::new(opened),
+            // Ending at a segment boundary, like `stream::decrypt_segments`
+            Err(_) if last && (size < constants::STREAM_TAG_SIZE || open(false).is_ok()) => {
+                return Err(Box::new(SticError::TruncatedPayload {
+                    offset: self.offset + size as u64,
+                }));
+            }
+            Err(_) => {
+                return Err(Box::new(SticError::CorruptPayload {
+                    offset: self.offset,
+                }))
+            }
+        };
+        plaintext.extend_from_slice(&opened);
+
+        self.consumed += size;
+        self.index += 1;
+        self.offset += size as u64;
+        Ok(())
+    }
+
+    fn compact(&mut self) {
+        self.pending.drain(..self.consumed);
+        self.consumed = 0;
+    }
+}
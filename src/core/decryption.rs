@@ -18,76 +18,189 @@ Copyright (C) 2022 Salvador Bravo Garnica
 use std::{
     error::Error,
     fs::File,
-    io::{prelude::*, Write},
-    path::Path,
+    io::{self, prelude::*, BufReader, Write},
+    path::{Path, PathBuf},
 };
 
-use crate::{constants, utils};
-
-use openssl::{
-    hash::{hash, MessageDigest},
-    memcmp,
-    pkey::PKey,
-    sign::Signer,
-    symm::{decrypt, Cipher, Crypter, Mode},
+use crate::{
+    backend::{self, Digest},
+    constants,
+    core::{stream, stream::SegmentCipher, Options},
+    error::SticError,
+    format::{Header, Token, Version},
+    utils::{self, ArmorReader, Phase, SecureBuffer},
 };
 
-use zeroize::Zeroizing;
-
 pub fn decrypt_file(
     input_path: &Path,
     password: &str,
     output_path: &Path,
 ) -> Result<(), Box<dyn Error>> {
-    let mut input_file = File::open(input_path)?;
+    decrypt_file_with(input_path, password, output_path, &Options::new())
+}
 
-    let tar_path = utils::TmpPath::new(input_path.parent().unwrap());
-    let mut tar_file = File::create(&tar_path.path())?;
+pub fn decrypt_file_with(
+    input_path: &Path,
+    password: &str,
+    output_path: &Path,
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let output_dir = input_path.parent().ok_or("Path has no parent directory")?;
+    decrypt_to(input_path, password, output_dir, Some(output_path), options)
+        .map_err(|e| options.cancelled_or(e))?;
+    Ok(())
+}
 
-    let mut header = [0u8; constants::HEADER_SIZE];
-    input_file.read_exact(&mut header)?;
+/// Decrypts `input_path` into `output_dir` under the name its content had when it was
+/// encrypted, taken from the first tar entry, and returns the restored path.
+pub fn decrypt_file_into(
+    input_path: &Path,
+    password: &str,
+    output_dir: &Path,
+    options: &Options,
+) -> Result<PathBuf, Box<dyn Error>> {
+    decrypt_to(input_path, password, output_dir, None, options).map_err(|e| options.cancelled_or(e))
+}
 
-    let token = &header[..constants::TOKEN_SIZE];
-    let iv = &header[constants::TOKEN_SIZE..];
+fn decrypt_to(
+    input_path: &Path,
+    password: &str,
+    output_dir: &Path,
+    output_path: Option<&Path>,
+    options: &Options,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let mut input_file = File::open(input_path)?;
+
+    // Armored files are decoded next to the output first, then decrypted like binary ones
+    let dearmored_path = utils::TmpPath::new(output_dir);
+    if utils::is_armored(&mut input_file)? {
+        let mut dearmored_file = File::create(dearmored_path.path())?;
+        io::copy(
+            &mut ArmorReader::new(BufReader::new(input_file)),
+            &mut dearmored_file,
+        )?;
+        input_file = File::open(dearmored_path.path())?;
+    }
 
-    let key = get_stream_key(password.as_bytes(), token)?;
+    let tar_path = utils::TmpPath::new(output_dir);
+    let mut tar_file = File::create(tar_path.path())?;
 
-    let result = _decrypt(&mut input_file, &key, iv, &mut tar_file);
+    let header = Header::read(&mut input_file)?;
+    let key = get_stream_key(password.as_bytes(), &header.token, options)?;
+    options.check()?;
 
-    if let Err(e) = result {
-        return Err(e);
+    if header.version() == Version::V1 {
+        decrypt_legacy(&mut input_file, &header, &key, &mut tar_file, options)?;
+    } else {
+        decrypt_segmented(&mut input_file, &header, &key, &mut tar_file, options)?;
     }
 
-    let tmp_dir = utils::TmpPath::new(input_path.parent().unwrap());
-    utils::from_tar(tar_path.path(), tmp_dir.path())?;
+    let compression = header.compression;
+    let tar_size = tar_file.metadata()?.len();
+    let tmp_dir = utils::TmpPath::new(output_dir);
+    let preserve = options.get_preserve();
+    utils::from_tar(
+        tar_path.path(),
+        tmp_dir.path(),
+        compression,
+        preserve,
+        &|done| {
+            options.report(Phase::Extract, done.min(tar_size), tar_size);
+            options.check_io()
+        },
+    )?;
+    options.report(Phase::Extract, tar_size, tar_size);
+    options.check()?;
+
+    let mut contents = std::fs::read_dir(tmp_dir.path())?;
+    let content = contents.next().ok_or("Encrypted archive is empty")??.path();
+
+    let output_path = match output_path {
+        Some(output_path) => output_path.to_path_buf(),
+        None => {
+            let output_path = output_dir.join(content.file_name().unwrap());
+            if output_path.symlink_metadata().is_ok() {
+                return Err(Box::from(format!(
+                    "{} already exists",
+                    output_path.display()
+                )));
+            }
+            output_path
+        }
+    };
+    std::fs::rename(&content, &output_path)?;
+
+    Ok(output_path)
+}
 
-    let mut contents = std::fs::read_dir(&tmp_dir.path())?;
-    let content = contents.next().unwrap().unwrap().path();
-    std::fs::rename(&content, output_path)?;
+fn decrypt_segmented(
+    input_file: &mut File,
+    header: &Header,
+    key: &[u8],
+    output_file: &mut File,
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    check_commitment(header, key)?;
+
+    let size = input_file.metadata()?.len() - header.size() as u64;
+    let mut payload = header.padding.unpad(output_file);
+
+    stream::decrypt_segments(
+        input_file,
+        &mut payload,
+        size,
+        &SegmentCipher {
+            aead: options.get_registry().aead(header.algorithm),
+            key,
+            nonce_prefix: &header.nonce,
+            aad: &header.aad(),
+            payload_offset: header.size() as u64,
+        },
+        options,
+    )?;
+    payload.finish()?;
+
+    Ok(())
+}
 
+pub(crate) fn check_commitment(header: &Header, key: &[u8]) -> Result<(), Box<dyn Error>> {
+    // VERSION_2 files predate key commitment
+    if let Some(commitment) = &header.commitment {
+        if !backend::current().eq(commitment, &stream::key_commitment(key)?) {
+            return Err(Box::new(SticError::CorruptHeader {
+                offset: (header.size() - commitment.len()) as u64,
+                reason: String::from("the key commitment does not match the key"),
+            }));
+        }
+    }
     Ok(())
 }
 
-fn _decrypt(
+/// Files written before segmentation carry a single GCM stream over the whole tar.
+fn decrypt_legacy(
     input_file: &mut File,
+    header: &Header,
     key: &[u8],
-    iv: &[u8],
     output_file: &mut File,
+    options: &Options,
 ) -> Result<(), Box<dyn Error>> {
-    let mut remaining_to_read = input_file.metadata()?.len() as usize;
-    remaining_to_read -= constants::HEADER_SIZE;
+    let file_size = input_file.metadata()?.len();
+    let mut remaining_to_read = file_size as usize - header.size();
+    let total = remaining_to_read as u64;
 
-    let stream_cipher = Cipher::aes_256_gcm();
+    if remaining_to_read < constants::STREAM_TAG_SIZE {
+        return Err(Box::new(SticError::TruncatedPayload { offset: file_size }));
+    }
 
     let mut read_buffer = vec![0u8; constants::BUFFER_SIZE];
-    let mut plaintext_buffer = vec![0u8; constants::BUFFER_SIZE + constants::BLOCK_SIZE];
+    let mut plaintext_buffer = SecureBuffer::new(constants::BUFFER_SIZE + constants::BLOCK_SIZE);
 
-    let mut crypter = Crypter::new(stream_cipher, Mode::Decrypt, key, Some(iv))?;
-
-    let aad = [constants::VERSION, iv].concat();
-    crypter.aad_update(&aad)?;
+    let mut decrypter =
+        backend::current().aes_256_gcm_decrypter(key, &header.nonce, &header.aad())?;
 
     while remaining_to_read != constants::STREAM_TAG_SIZE {
+        options.check()?;
+
         if remaining_to_read < constants::BUFFER_SIZE + constants::STREAM_TAG_SIZE {
             read_buffer.truncate(remaining_to_read - constants::STREAM_TAG_SIZE);
         }
@@ -95,59 +208,89 @@ fn _decrypt(
         let read_count = input_file.read(&mut read_buffer)?;
         remaining_to_read -= read_count;
 
-        let count = crypter.update(&read_buffer[..read_count], &mut plaintext_buffer)?;
+        let count = decrypter.update(&read_buffer[..read_count], &mut plaintext_buffer)?;
 
         output_file.write_all(&plaintext_buffer[..count])?;
+
+        options.report(Phase::Decrypt, total - remaining_to_read as u64, total);
     }
 
     let mut tag = [0u8; constants::STREAM_TAG_SIZE];
     input_file.read_exact(&mut tag)?;
 
-    crypter.set_tag(&tag)?;
-    let mut finalize = vec![0u8; constants::BLOCK_SIZE];
-    crypter.finalize(&mut finalize)?;
+    // The whole payload is a single GCM stream, so a failure can't be narrowed down further
+    decrypter
+        .finish(&tag)
+        .map_err(|_| SticError::CorruptPayload {
+            offset: header.size() as u64,
+        })?;
+    options.report(Phase::Decrypt, total, total);
 
     Ok(())
 }
 
-fn get_stream_key(password: &[u8], token: &[u8]) -> Result<Zeroizing<Vec<u8>>, Box<dyn Error>> {
-    let block_cipher = Cipher::aes_256_cbc();
-    let sha = MessageDigest::sha3_512();
+pub(crate) fn get_stream_key(
+    password: &[u8],
+    token: &Token,
+    options: &Options,
+) -> Result<SecureBuffer, Box<dyn Error>> {
+    if let Some(key) = options.get_stream_key(token) {
+        return Ok(key.clone());
+    }
+    let ttl = options.get_key_cache();
+    if let Some(password_key) = ttl.and_then(|_| utils::cached_key(&token.salt)) {
+        if let Some(key) = unwrap_stream_key(&password_key, token)? {
+            return Ok(key);
+        }
+    }
+    #[cfg(unix)]
+    if let Some(socket) = options.get_agent() {
+        // An agent that is gone or has nothing for this file leaves it to the password
+        if let Ok(Some(key)) = super::agent::agent_unwrap(socket, token) {
+            return Ok(key);
+        }
+    }
 
-    const VERSION_END: usize = constants::VERSION.len();
-    const SALT_END: usize = VERSION_END + constants::TOKEN_SALT_SIZE;
-    const IV_END: usize = SALT_END + constants::TOKEN_IV_SIZE;
-    const CIPHER_END: usize = IV_END + constants::TOKEN_CIPHERTEXT_SIZE;
-    const HMAC_END: usize = CIPHER_END + constants::TOKEN_HMAC_SIZE;
+    options.report(Phase::Kdf, 0, 1);
+    let password_key =
+        utils::key_from_password(options.get_registry().kdf(token.kdf), password, &token.salt)?;
+    options.report(Phase::Kdf, 1, 1);
 
-    let salt = &token[VERSION_END..SALT_END];
-    let iv = &token[SALT_END..IV_END];
-    let ciphertext = &token[IV_END..CIPHER_END];
-    let token_hmac = &token[CIPHER_END..HMAC_END];
+    let Some(key) = unwrap_stream_key(&password_key, token)? else {
+        std::thread::sleep(std::time::Duration::from_secs(
+            constants::INVALID_PASSWORD_TIMEOUT,
+        ));
+        return Err(Box::new(SticError::WrongPassword));
+    };
 
-    let password_key = utils::key_from_password(password, salt)?;
+    if let Some(ttl) = ttl {
+        // The cache only saves time, decryption goes on without it
+        let _ = utils::cache_key(&token.salt, &password_key, ttl);
+    }
+    Ok(key)
+}
+
+/// The stream key sealed in `token`, or `None` if `password_key` does not match its HMAC.
+pub(crate) fn unwrap_stream_key(
+    password_key: &[u8],
+    token: &Token,
+) -> Result<Option<SecureBuffer>, Box<dyn Error>> {
+    let backend = backend::current();
 
     let half_key = constants::TOKEN_KEY_SIZE / 2;
     let encryption_key = &password_key[..half_key];
     let signing_key = &password_key[half_key..];
 
-    let to_hash = [constants::VERSION, salt, iv, ciphertext].concat();
-    let hashed = hash(sha, &to_hash)?;
-
-    let signing_key = PKey::hmac(signing_key)?;
-    let mut signer = Signer::new(sha, &signing_key)?;
-    signer.update(&hashed)?;
-
-    let hmac = signer.sign_to_vec()?;
-
-    if !memcmp::eq(token_hmac, &hmac) {
-        std::thread::sleep(std::time::Duration::from_secs(
-            constants::INVALID_PASSWORD_TIMEOUT,
-        ));
-        return Err(Box::from("Invalid Password"));
+    let hashed = backend.sha3_512(&token.signed())?;
+    let hmac = backend.hmac(Digest::Sha3_512, signing_key, &hashed)?;
+    if !backend.eq(&token.hmac, &hmac) {
+        return Ok(None);
     }
 
-    let key = Zeroizing::new(decrypt(block_cipher, encryption_key, Some(iv), ciphertext)?);
-
-    Ok(key)
+    let key = SecureBuffer::from(backend.aes_256_cbc_decrypt(
+        encryption_key,
+        &token.iv,
+        &token.wrapped_key,
+    )?);
+    Ok(Some(key))
 }
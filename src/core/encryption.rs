@@ -18,124 +18,183 @@ Copyright (C) 2022 Salvador Bravo Garnica
 use std::{
     error::Error,
     fs::{remove_file, File},
-    io::{prelude::*, Write},
+    io::{BufWriter, Write},
     path::Path,
 };
 
-use crate::{constants, utils};
-
-use openssl::{
-    hash::{hash, MessageDigest},
-    pkey::PKey,
-    rand::rand_bytes,
-    sign::Signer,
-    symm::{encrypt, Cipher, Crypter, Mode},
+use crate::{
+    backend::{self, Digest},
+    constants,
+    core::{stream, stream::SegmentCipher, Options},
+    format::{Header, Token, Version},
+    utils::{self, ArmorWriter, Phase, SecureBuffer},
 };
 
-use zeroize::Zeroizing;
-
 pub fn encrypt_file(
     input_path: &Path,
     password: &str,
     output_path: &Path,
 ) -> Result<(), Box<dyn Error>> {
-    let mut output_file = File::create(output_path)?;
-
-    let tar_path = utils::TmpPath::new(input_path.parent().unwrap());
-
-    utils::to_tar(input_path, tar_path.path())?;
-
-    let mut input_file = File::open(&tar_path.path())?;
+    encrypt_file_with(input_path, password, output_path, &Options::new())
+}
 
-    let (key, token) = new_stream_key(password.as_bytes())?;
-    output_file.write_all(&token)?;
+pub fn encrypt_file_with(
+    input_path: &Path,
+    password: &str,
+    output_path: &Path,
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let mut output_file = File::create(output_path)?;
 
-    let result = _encrypt(&mut input_file, &key, &mut output_file);
+    let result = if options.get_armor() {
+        encrypt_armored(input_path, password, &mut output_file, options)
+    } else {
+        encrypt_to(input_path, password, &mut output_file, options)
+    };
 
     if let Err(e) = result {
+        drop(output_file);
         remove_file(output_path)?;
-        return Err(e);
+        return Err(options.cancelled_or(e));
     }
 
     Ok(())
 }
 
-fn _encrypt(
-    input_file: &mut File,
-    key: &[u8],
+fn encrypt_armored(
+    input_path: &Path,
+    password: &str,
     output_file: &mut File,
+    options: &Options,
 ) -> Result<(), Box<dyn Error>> {
-    let mut remaining_to_read = input_file.metadata()?.len() as usize;
-
-    let stream_cipher = Cipher::aes_256_gcm();
-
-    let mut iv = [0u8; constants::STREAM_IV_SIZE];
-    rand_bytes(&mut iv)?;
-
-    let mut read_buffer = Zeroizing::new(vec![0u8; constants::BUFFER_SIZE]);
-    let mut ciphertext_buffer = vec![0u8; constants::BUFFER_SIZE + constants::BLOCK_SIZE];
-
-    let aad = [constants::VERSION, &iv].concat();
-
-    let mut crypter = Crypter::new(stream_cipher, Mode::Encrypt, key, Some(&iv))?;
-    crypter.aad_update(&aad)?;
-
-    output_file.write_all(&iv)?;
-
-    while remaining_to_read != 0 {
-        let read_count = input_file.read(&mut read_buffer)?;
-        remaining_to_read -= read_count;
-
-        let count = crypter.update(&read_buffer[..read_count], &mut ciphertext_buffer)?;
-
-        output_file.write_all(&ciphertext_buffer[..count])?;
-    }
-
-    crypter.finalize(&mut ciphertext_buffer)?;
-
-    let mut tag = [0u8; constants::STREAM_TAG_SIZE];
-    crypter.get_tag(&mut tag)?;
-
-    output_file.write_all(&tag)?;
-
+    let mut armored = ArmorWriter::new(BufWriter::new(output_file))?;
+    encrypt_to(input_path, password, &mut armored, options)?;
+    armored.finish()?.flush()?;
     Ok(())
 }
 
-type KeyAndToken = (Zeroizing<Vec<u8>>, Vec<u8>);
-fn new_stream_key(password: &[u8]) -> Result<KeyAndToken, Box<dyn Error>> {
-    let block_cipher = Cipher::aes_256_cbc();
-    let sha = MessageDigest::sha3_512();
+fn encrypt_to<W: Write>(
+    input_path: &Path,
+    password: &str,
+    output_file: &mut W,
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let output_dir = input_path.parent().ok_or("Path has no parent directory")?;
+    let tar_path = utils::TmpPath::new(output_dir);
+
+    let symlinks = options.get_symlinks();
+    let compression = options.get_compression();
+    let level = options.get_compression_level();
+    let preserve = options.get_preserve();
+    let input_size = utils::to_tar(
+        input_path,
+        tar_path.path(),
+        compression,
+        level,
+        preserve,
+        symlinks,
+        &|done, total| {
+            options.report(Phase::Tar, done.min(total), total);
+            options.check_io()
+        },
+    )?;
+    options.report(Phase::Tar, input_size, input_size);
+
+    let input_file = File::open(tar_path.path())?;
+    let tar_size = input_file.metadata()?.len();
+
+    let padding = options.get_padding();
+    let size = padding.padded_size(tar_size);
+    let mut payload = padding.pad(input_file, tar_size);
+
+    let (key, token) = new_stream_key(password.as_bytes(), options)?;
+    options.check()?;
+
+    let algorithm = options.get_algorithm();
+
+    let mut nonce_prefix = vec![0u8; algorithm.nonce_prefix_size()];
+    backend::current().fill_random(&mut nonce_prefix)?;
+
+    let header = Header {
+        token,
+        algorithm,
+        compression,
+        padding,
+        nonce: nonce_prefix,
+        commitment: Some(stream::key_commitment(&key)?),
+    };
+    output_file.write_all(&header.serialize())?;
+
+    stream::encrypt_segments(
+        &mut payload,
+        output_file,
+        size,
+        &SegmentCipher {
+            aead: options.get_registry().aead(algorithm),
+            key: &key,
+            nonce_prefix: &header.nonce,
+            aad: &header.aad(),
+            payload_offset: header.size() as u64,
+        },
+        options,
+    )
+}
+
+pub(crate) type KeyAndToken = (SecureBuffer, Token);
+pub(crate) fn new_stream_key(
+    password: &[u8],
+    options: &Options,
+) -> Result<KeyAndToken, Box<dyn Error>> {
+    let backend = backend::current();
 
     let mut salt = [0u8; constants::TOKEN_SALT_SIZE];
-    rand_bytes(&mut salt)?;
+    backend.fill_random(&mut salt)?;
 
     let mut iv = [0u8; constants::TOKEN_IV_SIZE];
-    rand_bytes(&mut iv)?;
+    backend.fill_random(&mut iv)?;
 
-    let mut key = Zeroizing::new(vec![0u8; constants::STREAM_KEY_SIZE]);
-    rand_bytes(&mut key)?;
+    let mut key = SecureBuffer::new(constants::STREAM_KEY_SIZE);
+    backend.fill_random(&mut key)?;
 
-    let password_key = utils::key_from_password(password, &salt)?;
+    let token = wrap_stream_key(password, salt, iv, &key, options)?;
+    Ok((key, token))
+}
+
+/// Seals `key` into a token that `password` opens, with the given salt and IV.
+pub(crate) fn wrap_stream_key(
+    password: &[u8],
+    salt: [u8; constants::TOKEN_SALT_SIZE],
+    iv: [u8; constants::TOKEN_IV_SIZE],
+    key: &[u8],
+    options: &Options,
+) -> Result<Token, Box<dyn Error>> {
+    let backend = backend::current();
+
+    options.report(Phase::Kdf, 0, 1);
+    let kdf = options.get_registry().kdf(options.get_kdf());
+    let password_key = utils::key_from_password(kdf, password, &salt)?;
+    options.report(Phase::Kdf, 1, 1);
+    if let Some(ttl) = options.get_key_cache() {
+        let _ = utils::cache_key(&salt, &password_key, ttl);
+    }
 
     let half_key = constants::TOKEN_KEY_SIZE / 2;
     let encryption_key = &password_key[..half_key];
     let signing_key = &password_key[half_key..];
 
-    let ciphertext = encrypt(block_cipher, encryption_key, Some(&iv), &key)?;
-
-    let to_hash = [constants::VERSION, &salt, &iv, &ciphertext].concat();
-    let hashed = hash(sha, &to_hash)?;
-
-    let signing_key = PKey::hmac(signing_key)?;
-    let mut signer = Signer::new(sha, &signing_key)?;
-    signer.update(&hashed)?;
-
-    let hmac = signer.sign_to_vec()?;
-
-    let mut token = to_hash;
-    for byte in hmac {
-        token.push(byte);
-    }
-
-    Ok((key, token))
+    let ciphertext = backend.aes_256_cbc_encrypt(encryption_key, &iv, key)?;
+
+    let mut token = Token {
+        version: Version::CURRENT,
+        kdf: options.get_kdf(),
+        salt,
+        iv,
+        wrapped_key: ciphertext.try_into().unwrap(),
+        hmac: [0u8; constants::TOKEN_HMAC_SIZE],
+    };
+    let hashed = backend.sha3_512(&token.signed())?;
+    let hmac = backend.hmac(Digest::Sha3_512, signing_key, &hashed)?;
+    token.hmac.copy_from_slice(&hmac);
+
+    Ok(token)
 }
@@ -0,0 +1,128 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    backend::{self, Digest},
+    constants,
+    core::{
+        encryption::{self, KeyAndToken},
+        Decryptor, Encryptor, Options,
+    },
+    format::Version,
+    utils::SecureBuffer,
+};
+
+use zeroize::Zeroizing;
+
+/// Reads the key shared by everyone who checks out the repository. It stands in for the
+/// password, so it should be random: `head -c 32 /dev/urandom > keyfile`.
+pub fn read_keyfile(path: &Path) -> Result<SecureBuffer, Box<dyn Error>> {
+    let key = SecureBuffer::from(std::fs::read(path)?);
+    if key.len() < constants::KEYFILE_MIN_SIZE {
+        return Err(Box::from(format!(
+            "Keyfile must hold at least {} bytes",
+            constants::KEYFILE_MIN_SIZE
+        )));
+    }
+    Ok(key)
+}
+
+/// Git `clean` filter: encrypts the working tree file on `reader` for the repository.
+///
+/// With `deterministic`, every value that is normally random is derived from the keyfile and
+/// the content, so an unchanged file encrypts to the same bytes and does not show up as
+/// modified. The price is that anyone who can read the repository sees which versions of a file
+/// are identical.
+pub fn git_clean<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    keyfile: &[u8],
+    deterministic: bool,
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let options = &options.clone().keyfile();
+    if !deterministic {
+        let (key, token) = encryption::new_stream_key(keyfile, options)?;
+        return Encryptor::with_key(key, token, options)?.copy(reader, writer);
+    }
+
+    let mut content = Zeroizing::new(Vec::new());
+    reader.read_to_end(&mut content)?;
+
+    let message = Zeroizing::new([constants::CONVERGENT_LABEL, &content].concat());
+    let seed = SecureBuffer::from(backend::current().hmac(Digest::Sha3_512, keyfile, &message)?);
+    let ((key, token), nonce_prefix) = convergent_keys(keyfile, &seed, options)?;
+    Encryptor::with_nonce_prefix(key, token, nonce_prefix, options)?.copy(&mut &content[..], writer)
+}
+
+/// Git `smudge` filter and `textconv` helper: decrypts what `git_clean` stored. Content that
+/// does not start with a file version, such as a version committed before the filter was set
+/// up, is passed through unchanged.
+pub fn git_smudge<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    keyfile: &[u8],
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let mut start = Vec::with_capacity(constants::MAX_HEADER_SIZE);
+    reader
+        .take(constants::MAX_HEADER_SIZE as u64)
+        .read_to_end(&mut start)?;
+
+    // Anything that starts like an encrypted file is decrypted, so that a truncated or damaged
+    // blob fails instead of being checked out as ciphertext
+    let mut reader = (&start[..]).chain(reader);
+    let version = start.get(..constants::VERSION.len()).unwrap_or(&start);
+    if Version::from_bytes(version).is_err() {
+        io::copy(&mut reader, writer)?;
+        return Ok(());
+    }
+    Decryptor::with_password(keyfile, options).copy(&mut reader, writer)
+}
+
+/// The salt, IV, stream key and nonce prefix for `seed`, each from its own HMAC label.
+fn convergent_keys(
+    keyfile: &[u8],
+    seed: &[u8],
+    options: &Options,
+) -> Result<(KeyAndToken, Vec<u8>), Box<dyn Error>> {
+    let derive = |label: &str, size: usize| -> Result<SecureBuffer, Box<dyn Error>> {
+        let mut value = backend::current().hmac(Digest::Sha3_512, seed, label.as_bytes())?;
+        value.truncate(size);
+        Ok(SecureBuffer::from(value))
+    };
+
+    let salt = derive("salt", constants::TOKEN_SALT_SIZE)?;
+    let iv = derive("iv", constants::TOKEN_IV_SIZE)?;
+    let key = derive("stream key", constants::STREAM_KEY_SIZE)?;
+    let nonce_prefix = derive("nonce", options.get_algorithm().nonce_prefix_size())?;
+
+    let token = encryption::wrap_stream_key(
+        keyfile,
+        salt[..].try_into().unwrap(),
+        iv[..].try_into().unwrap(),
+        &key,
+        options,
+    )?;
+    Ok(((key, token), nonce_prefix.to_vec()))
+}
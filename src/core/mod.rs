@@ -14,8 +14,38 @@ If not, see <https://www.gnu.org/licenses/>.
 
 Copyright (C) 2022 Salvador Bravo Garnica
 */
+#[cfg(unix)]
+mod agent;
+mod algorithm;
+#[cfg(feature = "async")]
+mod async_io;
+mod bench;
+mod bytes;
 mod decryption;
 mod encryption;
+mod filter;
+mod options;
+mod registry;
+mod stream;
 
-pub use decryption::decrypt_file;
-pub use encryption::encrypt_file;
+#[cfg(unix)]
+pub(crate) use agent::agent_unwrap;
+#[cfg(unix)]
+pub use agent::{agent_add, agent_identities, agent_lock, agent_socket, run_agent};
+pub use algorithm::Algorithm;
+#[cfg(feature = "async")]
+pub use async_io::{
+    decrypt_file_async, decrypt_file_into_async, encrypt_file_async, AsyncDecryptReader,
+    AsyncEncryptWriter,
+};
+pub use bench::{bench, BenchResult};
+pub use bytes::{
+    decrypt_bytes, decrypt_stream, encrypt_bytes, encrypt_stream, Decryptor, Encryptor,
+};
+pub(crate) use decryption::unwrap_stream_key;
+pub use decryption::{decrypt_file, decrypt_file_into, decrypt_file_with};
+pub use encryption::{encrypt_file, encrypt_file_with};
+pub use filter::{git_clean, git_smudge, read_keyfile};
+pub use options::Options;
+pub(crate) use registry::kdf_from_id;
+pub use registry::{Aead, PasswordKdf, Registry};
@@ -0,0 +1,241 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{error::Error, io, path::PathBuf, sync::Arc, time::Duration};
+
+use crate::{
+    constants,
+    core::{Algorithm, Registry},
+    error::SticError,
+    format::Token,
+    utils::{CancelToken, Compression, Padding, Phase, Preserve, Progress, SecureBuffer, Symlinks},
+};
+
+/// Optional settings for `encrypt_file_with` and `decrypt_file_with`.
+#[derive(Clone, Default)]
+pub struct Options {
+    progress: Option<Arc<dyn Progress>>,
+    cancel: Option<CancelToken>,
+    threads: usize,
+    algorithm: Algorithm,
+    compression: Compression,
+    compression_level: Option<u32>,
+    padding: Padding,
+    preserve: Preserve,
+    symlinks: Symlinks,
+    armor: bool,
+    registry: Registry,
+    key_cache: Option<Duration>,
+    agent: Option<PathBuf>,
+    keyfile: bool,
+    stream_key: Option<(Token, SecureBuffer)>,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn progress(mut self, progress: Arc<dyn Progress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    pub fn cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Number of worker threads used for segment encryption, `0` (the default) uses one per
+    /// available core.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Cipher used for new files, AES-256-GCM by default. Decryption reads it from the header.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub(crate) fn get_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Compresses the tar before encryption. Decryption reads it from the header.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Overrides the compression's default level.
+    pub fn compression_level(mut self, level: u32) -> Self {
+        self.compression_level = Some(level);
+        self
+    }
+
+    /// Pads the encrypted payload to hide the exact content size.
+    pub fn padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// File metadata kept in the archive and restored on decryption, everything by default.
+    pub fn preserve(mut self, preserve: Preserve) -> Self {
+        self.preserve = preserve;
+        self
+    }
+
+    /// Whether symlinks inside the encrypted path are stored as links (the default) or replaced by
+    /// what they point to.
+    pub fn symlinks(mut self, symlinks: Symlinks) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Writes the encrypted file as base64 text between BEGIN and END markers. Decryption
+    /// detects armored files by their marker.
+    pub fn armor(mut self, armor: bool) -> Self {
+        self.armor = armor;
+        self
+    }
+
+    /// Cipher and KDF implementations, the built-in ones by default.
+    pub fn registry(mut self, registry: Registry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Keeps password-derived keys in the session keyring for `ttl`, so that files seen again
+    /// within it, by salt, skip the KDF. Only available on Linux, ignored elsewhere.
+    ///
+    /// A cached key opens every file with its salt whatever password is passed, so within `ttl`
+    /// the password is not checked at all, and any process of the session that enables the
+    /// cache can decrypt those files. Leave it off where the password is what authorizes the
+    /// caller.
+    pub fn key_cache(mut self, ttl: Duration) -> Self {
+        self.key_cache = Some(ttl);
+        self
+    }
+
+    /// Asks the agent listening on `socket` to open files before falling back to the password.
+    pub fn agent(mut self, socket: PathBuf) -> Self {
+        self.agent = Some(socket);
+        self
+    }
+
+    /// Wraps the stream key for a keyfile, which skips the password stretching.
+    pub(crate) fn keyfile(mut self) -> Self {
+        self.keyfile = true;
+        self
+    }
+
+    /// Opens `token` with `key`, unwrapped beforehand, without asking anything else for it.
+    pub(crate) fn stream_key(mut self, token: Token, key: SecureBuffer) -> Self {
+        self.stream_key = Some((token, key));
+        self
+    }
+
+    pub(crate) fn get_compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub(crate) fn get_compression_level(&self) -> u32 {
+        self.compression_level
+            .unwrap_or_else(|| self.compression.default_level())
+    }
+
+    pub(crate) fn get_padding(&self) -> Padding {
+        self.padding
+    }
+
+    pub(crate) fn get_preserve(&self) -> Preserve {
+        self.preserve
+    }
+
+    pub(crate) fn get_symlinks(&self) -> Symlinks {
+        self.symlinks
+    }
+
+    pub(crate) fn get_armor(&self) -> bool {
+        self.armor
+    }
+
+    pub(crate) fn get_registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    pub(crate) fn get_kdf(&self) -> u8 {
+        if self.keyfile {
+            constants::KDF_HMAC_SHA3_512
+        } else {
+            constants::KDF_PBKDF2_HMAC_SHA3_512
+        }
+    }
+
+    pub(crate) fn get_stream_key(&self, token: &Token) -> Option<&SecureBuffer> {
+        let (known, key) = self.stream_key.as_ref()?;
+        (known == token).then_some(key)
+    }
+
+    pub(crate) fn get_key_cache(&self) -> Option<Duration> {
+        self.key_cache
+    }
+
+    pub(crate) fn get_agent(&self) -> Option<&PathBuf> {
+        self.agent.as_ref()
+    }
+
+    pub(crate) fn thread_count(&self) -> usize {
+        if self.threads != 0 {
+            return self.threads;
+        }
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    }
+
+    pub(crate) fn report(&self, phase: Phase, done: u64, total: u64) {
+        if let Some(progress) = &self.progress {
+            progress.update(phase, done, total);
+        }
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    pub(crate) fn check(&self) -> Result<(), SticError> {
+        if self.is_cancelled() {
+            return Err(SticError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Like `check`, for callbacks running inside `Read`/`Write` implementations.
+    pub(crate) fn check_io(&self) -> io::Result<()> {
+        self.check().map_err(io::Error::other)
+    }
+
+    /// Replaces `error` with `SticError::Cancelled` when it was caused by a cancellation, since
+    /// errors raised inside tar and I/O adapters lose their original type.
+    pub(crate) fn cancelled_or(&self, error: Box<dyn Error>) -> Box<dyn Error> {
+        if self.is_cancelled() {
+            return Box::new(SticError::Cancelled);
+        }
+        error
+    }
+}
@@ -0,0 +1,191 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{collections::BTreeMap, error::Error, sync::Arc};
+
+use crate::{
+    backend::{self, Digest},
+    constants,
+    core::Algorithm,
+    utils::SecureBuffer,
+};
+
+/// A segment cipher. Implementations registered under the id of an `Algorithm` replace the
+/// built-in one, e.g. to route AES-256-GCM through a validated provider, and must produce the
+/// same bytes so that files stay interchangeable. The single GCM stream of `VERSION_1` files is
+/// always decrypted by the built-in backend.
+pub trait Aead: Send + Sync {
+    /// The `Algorithm` id stored in the header.
+    fn id(&self) -> u8;
+
+    /// Returns `ciphertext || tag`, with a 16 byte tag.
+    fn seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+
+    /// Reverses `seal`, failing if the tag does not match or `sealed` is shorter than it. The
+    /// plaintext is returned in ordinary memory, which callers move into a `SecureBuffer`: it is
+    /// zeroized from then on, but not locked while the cipher writes it.
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        sealed: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+}
+
+/// Derives the key that wraps the stream key from the password.
+pub trait PasswordKdf: Send + Sync {
+    /// The id stored in the token from `VERSION_4` on: `KDF_PBKDF2_HMAC_SHA3_512` with
+    /// `PBKDF2_HMAC_ITERATIONS` rounds for passwords, which earlier versions imply, or a single
+    /// `KDF_HMAC_SHA3_512` for keyfiles.
+    fn id(&self) -> u8;
+
+    /// Fills the whole of `key`.
+    fn derive(&self, password: &[u8], salt: &[u8], key: &mut [u8]) -> Result<(), Box<dyn Error>>;
+}
+
+/// The implementations used for each id, the built-in ones unless replaced.
+#[derive(Clone)]
+pub struct Registry {
+    aeads: BTreeMap<u8, Arc<dyn Aead>>,
+    kdfs: BTreeMap<u8, Arc<dyn PasswordKdf>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        let mut registry = Registry {
+            aeads: BTreeMap::new(),
+            kdfs: BTreeMap::new(),
+        };
+        for algorithm in Algorithm::ALL {
+            registry
+                .aeads
+                .insert(algorithm.id(), Arc::new(Builtin(algorithm)));
+        }
+        registry
+            .kdfs
+            .insert(constants::KDF_PBKDF2_HMAC_SHA3_512, Arc::new(Pbkdf2));
+        registry
+            .kdfs
+            .insert(constants::KDF_HMAC_SHA3_512, Arc::new(Hmac));
+        registry
+    }
+
+    /// Replaces the implementation of the algorithm with `aead`'s id.
+    pub fn register_aead(&mut self, aead: Arc<dyn Aead>) -> Result<(), Box<dyn Error>> {
+        let id = Algorithm::from_id(aead.id())?.id();
+        self.aeads.insert(id, aead);
+        Ok(())
+    }
+
+    /// Replaces the implementation of the KDF with `kdf`'s id.
+    pub fn register_kdf(&mut self, kdf: Arc<dyn PasswordKdf>) -> Result<(), Box<dyn Error>> {
+        let id = kdf.id();
+        if !self.kdfs.contains_key(&id) {
+            return Err(Box::from(format!("Unknown KDF id {id}")));
+        }
+        self.kdfs.insert(id, kdf);
+        Ok(())
+    }
+
+    pub(crate) fn aead(&self, algorithm: Algorithm) -> &dyn Aead {
+        self.aeads[&algorithm.id()].as_ref()
+    }
+
+    /// Ids come from `kdf_from_id`, so they are all registered.
+    pub(crate) fn kdf(&self, id: u8) -> &dyn PasswordKdf {
+        self.kdfs[&id].as_ref()
+    }
+}
+
+pub(crate) fn kdf_from_id(id: u8) -> Result<u8, Box<dyn Error>> {
+    match id {
+        constants::KDF_PBKDF2_HMAC_SHA3_512 | constants::KDF_HMAC_SHA3_512 => Ok(id),
+        _ => Err(Box::from(format!("Unknown KDF id {id}"))),
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Builtin(Algorithm);
+
+impl Aead for Builtin {
+    fn id(&self) -> u8 {
+        self.0.id()
+    }
+
+    fn seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        self.0.seal(key, nonce, aad, plaintext)
+    }
+
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        sealed: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        self.0.open(key, nonce, aad, sealed)
+    }
+}
+
+struct Pbkdf2;
+
+impl PasswordKdf for Pbkdf2 {
+    fn id(&self) -> u8 {
+        constants::KDF_PBKDF2_HMAC_SHA3_512
+    }
+
+    fn derive(&self, password: &[u8], salt: &[u8], key: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        backend::current().pbkdf2(password, salt, constants::PBKDF2_HMAC_ITERATIONS, key)
+    }
+}
+
+/// For keyfiles, whose 32 random bytes or more leave nothing for stretching to protect.
+struct Hmac;
+
+impl PasswordKdf for Hmac {
+    fn id(&self) -> u8 {
+        constants::KDF_HMAC_SHA3_512
+    }
+
+    fn derive(&self, password: &[u8], salt: &[u8], key: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        let derived =
+            SecureBuffer::from(backend::current().hmac(Digest::Sha3_512, password, salt)?);
+        if key.len() > derived.len() {
+            return Err(Box::from("Key too long for HMAC-SHA3-512"));
+        }
+        key.copy_from_slice(&derived[..key.len()]);
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    io::{Read, Write},
+    ops::{Deref, DerefMut},
+    sync::{
+        mpsc::{channel, sync_channel},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    backend::{self, Digest},
+    constants,
+    core::{Aead, Options},
+    error::SticError,
+    utils::{Phase, SecureBuffer},
+};
+
+/// Everything needed to seal or open the segments of one file.
+pub struct SegmentCipher<'a> {
+    pub aead: &'a dyn Aead,
+    pub key: &'a [u8],
+    pub nonce_prefix: &'a [u8],
+    pub aad: &'a [u8],
+    /// Position of the first segment in the file, for error reports.
+    pub payload_offset: u64,
+}
+
+type SegmentResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Buffer a segment is read into: a `SecureBuffer` for plaintext, a plain `Vec` for ciphertext,
+/// which needs neither locking nor zeroing.
+trait SegmentBuffer: DerefMut<Target = [u8]> + Send {
+    fn zeroed(length: usize) -> Self;
+}
+
+impl SegmentBuffer for SecureBuffer {
+    fn zeroed(length: usize) -> Self {
+        SecureBuffer::new(length)
+    }
+}
+
+impl SegmentBuffer for Vec<u8> {
+    fn zeroed(length: usize) -> Self {
+        vec![0; length]
+    }
+}
+
+/// Encrypts `size` bytes from `reader` as a sequence of `SEGMENT_SIZE` segments, each sealed
+/// under its own nonce, so that segments can be processed independently across threads.
+pub fn encrypt_segments<R: Read + Send, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    size: u64,
+    cipher: &SegmentCipher,
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    run_pipeline::<SecureBuffer, _, _, _, _>(
+        reader,
+        writer,
+        size,
+        constants::SEGMENT_SIZE,
+        Phase::Encrypt,
+        options,
+        |index, plaintext, last| {
+            let nonce = segment_nonce(cipher.nonce_prefix, index, last);
+            cipher.aead.seal(cipher.key, &nonce, cipher.aad, plaintext)
+        },
+    )
+}
+
+/// Reverses `encrypt_segments`; `size` is the length of the encrypted payload, tags included.
+pub fn decrypt_segments<R: Read + Send, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    size: u64,
+    cipher: &SegmentCipher,
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    run_pipeline::<Vec<u8>, _, _, _, _>(
+        reader,
+        writer,
+        size,
+        constants::ENCRYPTED_SEGMENT_SIZE,
+        Phase::Decrypt,
+        options,
+        |index, segment, last| {
+            let open = |last| {
+                let nonce = segment_nonce(cipher.nonce_prefix, index, last);
+                cipher.aead.open(cipher.key, &nonce, cipher.aad, segment)
+            };
+            let offset = cipher.payload_offset + index * constants::ENCRYPTED_SEGMENT_SIZE as u64;
+
+            match open(last) {
+                // `open` leaves the plaintext in ordinary memory, zeroized once it is moved here
+                Ok(plaintext) => Ok(SecureBuffer::from(plaintext)),
+                // A payload cut at a segment boundary ends with a segment not sealed as the last
+                Err(_)
+                    if last
+                        && (segment.len() < constants::STREAM_TAG_SIZE || open(false).is_ok()) =>
+                {
+                    Err(Box::new(SticError::TruncatedPayload {
+                        offset: cipher.payload_offset + size,
+                    }))
+                }
+                Err(_) => Err(Box::new(SticError::CorruptPayload { offset })),
+            }
+        },
+    )
+}
+
+/// Binds the file to its stream key: AES-GCM and ChaCha20-Poly1305 are not key-committing, so
+/// without it a crafted payload could open under two different keys.
+pub fn key_commitment(key: &[u8]) -> Result<[u8; constants::KEY_COMMITMENT_SIZE], Box<dyn Error>> {
+    let commitment =
+        backend::current().hmac(Digest::Sha3_256, key, constants::KEY_COMMITMENT_LABEL)?;
+    Ok(commitment.try_into().unwrap())
+}
+
+/// Nonce layout: `prefix || segment index (u64, big endian) || last segment flag`.
+///
+/// Every segment gets a distinct nonce under a key that is random per file, so a single GCM
+/// invocation never exceeds one segment and the file size is bounded only by the counter.
+pub(crate) fn segment_nonce(prefix: &[u8], index: u64, last: bool) -> Vec<u8> {
+    let mut nonce = Vec::with_capacity(prefix.len() + constants::SEGMENT_NONCE_SUFFIX_SIZE);
+    nonce.extend_from_slice(prefix);
+    nonce.extend_from_slice(&index.to_be_bytes());
+    nonce.push(last as u8);
+    nonce
+}
+
+fn segment_count(size: u64, segment_size: usize) -> u64 {
+    size.div_ceil(segment_size as u64).max(1)
+}
+
+/// Reads `size` bytes in chunks of `segment_size` into `I` buffers, runs `transform` over them on
+/// `options.thread_count()` workers and writes the results in their original order.
+///
+/// At most `thread_count * SEGMENTS_PER_THREAD` segments are held in memory at any time.
+fn run_pipeline<I, R, W, O, F>(
+    reader: &mut R,
+    writer: &mut W,
+    size: u64,
+    segment_size: usize,
+    phase: Phase,
+    options: &Options,
+    transform: F,
+) -> Result<(), Box<dyn Error>>
+where
+    I: SegmentBuffer,
+    R: Read + Send,
+    W: Write,
+    O: Deref<Target = [u8]> + Send,
+    F: Fn(u64, &[u8], bool) -> SegmentResult<O> + Sync,
+{
+    let count = segment_count(size, segment_size);
+    let threads = options.thread_count();
+    let in_flight = threads * constants::SEGMENTS_PER_THREAD;
+
+    thread::scope(|scope| {
+        let (job_sender, job_receiver) = sync_channel::<(u64, I)>(in_flight);
+        let (result_sender, result_receiver) = channel::<(u64, SegmentResult<O>)>();
+        let (slot_sender, slot_receiver) = sync_channel::<()>(in_flight);
+        for _ in 0..in_flight {
+            slot_sender.send(())?;
+        }
+
+        let reader_results = result_sender.clone();
+        scope.spawn(move || {
+            for index in 0..count {
+                if slot_receiver.recv().is_err() {
+                    return;
+                }
+                if options.is_cancelled() {
+                    let _ = reader_results.send((index, Err(Box::new(SticError::Cancelled))));
+                    return;
+                }
+
+                let offset = index * segment_size as u64;
+                let length = (size - offset).min(segment_size as u64) as usize;
+                let mut buffer = I::zeroed(length);
+                if let Err(e) = reader.read_exact(&mut buffer) {
+                    let _ = reader_results.send((index, Err(Box::new(e))));
+                    return;
+                }
+                if job_sender.send((index, buffer)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for _ in 0..threads {
+            let job_receiver = Arc::clone(&job_receiver);
+            let result_sender = result_sender.clone();
+            let transform = &transform;
+            scope.spawn(move || loop {
+                let job = job_receiver.lock().unwrap().recv();
+                let Ok((index, buffer)) = job else {
+                    return;
+                };
+                let result = transform(index, &buffer, index == count - 1);
+                if result_sender.send((index, result)).is_err() {
+                    return;
+                }
+            });
+        }
+        drop(result_sender);
+
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        let mut done = 0;
+        while next < count {
+            let (index, result) = result_receiver.recv()?;
+            pending.insert(index, result);
+
+            while let Some(result) = pending.remove(&next) {
+                // The reader may be done already, with the last segments in flight
+                options.check()?;
+                let output = result.map_err(|e| e as Box<dyn Error>)?;
+                writer.write_all(&output)?;
+
+                done = (done + segment_size as u64).min(size);
+                options.report(phase, done, size);
+
+                let _ = slot_sender.send(());
+                next += 1;
+            }
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,62 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{error::Error, fmt};
+
+/// Failures callers may want to tell apart, recovered from a `Box<dyn Error>` with `downcast_ref`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SticError {
+    Cancelled,
+    /// The header can't be parsed, or does not match the key it wraps. `offset` is the byte of
+    /// the file where the problem is.
+    CorruptHeader {
+        offset: u64,
+        reason: String,
+    },
+    /// The token HMAC does not match the key derived from the password.
+    WrongPassword,
+    /// The segment starting at byte `offset` failed authentication.
+    CorruptPayload {
+        offset: u64,
+    },
+    /// The file ends at byte `offset`, before the last segment of its payload.
+    TruncatedPayload {
+        offset: u64,
+    },
+}
+
+impl fmt::Display for SticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SticError::Cancelled => write!(f, "Operation cancelled"),
+            SticError::CorruptHeader { offset, reason } => {
+                write!(f, "Corrupt header at byte {offset}: {reason}")
+            }
+            SticError::WrongPassword => write!(f, "Invalid Password"),
+            SticError::CorruptPayload { offset } => write!(
+                f,
+                "Corrupt payload: the segment at byte {offset} failed authentication"
+            ),
+            SticError::TruncatedPayload { offset } => write!(
+                f,
+                "Truncated payload: the file ends at byte {offset}, before its last segment"
+            ),
+        }
+    }
+}
+
+impl Error for SticError {}
@@ -0,0 +1,249 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+//! C API, enabled by the `ffi` feature. The shared library is built with
+//! `cargo rustc --release --lib --crate-type cdylib --features ffi`, and `include/stic.h` is
+//! generated from this file by the build script.
+//!
+//! Every function returns a `SticStatus`. On failure, `stic_last_error` describes the error until
+//! the next call on the same thread.
+
+use std::{
+    cell::RefCell,
+    error::Error,
+    ffi::{c_char, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    ptr, slice,
+};
+
+use crate::{
+    core::{decrypt_bytes, decrypt_file_into, encrypt_bytes, encrypt_file_with, Options},
+    error::SticError,
+};
+
+/// Result of every call. `Ok` to `CorruptPayload` have the values the CLI exits with; the CLI
+/// also exits with 4 for a truncated payload and with 130 when cancelled, which have values of
+/// their own here.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SticStatus {
+    Ok = 0,
+    Error = 1,
+    WrongPassword = 2,
+    CorruptHeader = 3,
+    CorruptPayload = 4,
+    TruncatedPayload = 5,
+    Cancelled = 6,
+    /// A pointer was null or a string was not valid UTF-8.
+    InvalidArgument = 7,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Description of the last failed call on this thread, or null if it succeeded. The string is
+/// owned by the library and valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn stic_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| match &*error.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Encrypts the file or directory at `input_path` into `output_path`.
+///
+/// # Safety
+///
+/// The arguments must be null or point to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn stic_encrypt_file(
+    input_path: *const c_char,
+    password: *const c_char,
+    output_path: *const c_char,
+) -> SticStatus {
+    status(|| {
+        let input_path = to_str(input_path)?;
+        let password = to_str(password)?;
+        let output_path = to_str(output_path)?;
+        encrypt_file_with(
+            Path::new(input_path),
+            password,
+            Path::new(output_path),
+            &Options::new(),
+        )
+    })
+}
+
+/// Decrypts the file at `input_path` into the existing directory `output_dir`, under the name it
+/// was encrypted from.
+///
+/// # Safety
+///
+/// The arguments must be null or point to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn stic_decrypt_file(
+    input_path: *const c_char,
+    password: *const c_char,
+    output_dir: *const c_char,
+) -> SticStatus {
+    status(|| {
+        let input_path = to_str(input_path)?;
+        let password = to_str(password)?;
+        let output_dir = to_str(output_dir)?;
+        decrypt_file_into(
+            Path::new(input_path),
+            password,
+            Path::new(output_dir),
+            &Options::new(),
+        )
+        .map(|_| ())
+    })
+}
+
+/// Encrypts `length` bytes at `data`. On success `*output` and `*output_length` hold the
+/// ciphertext, to be released with `stic_buffer_free`.
+///
+/// # Safety
+///
+/// `data` must point to `length` readable bytes (or may be null if `length` is 0), `password`
+/// to a NUL-terminated string, and `output` and `output_length` to writable locations.
+#[no_mangle]
+pub unsafe extern "C" fn stic_encrypt_buffer(
+    data: *const u8,
+    length: usize,
+    password: *const c_char,
+    output: *mut *mut u8,
+    output_length: *mut usize,
+) -> SticStatus {
+    status(|| {
+        let data = to_slice(data, length)?;
+        let password = to_str(password)?;
+        let ciphertext = encrypt_bytes(data, password, &Options::new())?;
+        write_buffer(ciphertext, output, output_length)
+    })
+}
+
+/// Decrypts `length` bytes at `data`, the counterpart of `stic_encrypt_buffer`.
+///
+/// # Safety
+///
+/// Same as `stic_encrypt_buffer`.
+#[no_mangle]
+pub unsafe extern "C" fn stic_decrypt_buffer(
+    data: *const u8,
+    length: usize,
+    password: *const c_char,
+    output: *mut *mut u8,
+    output_length: *mut usize,
+) -> SticStatus {
+    status(|| {
+        let data = to_slice(data, length)?;
+        let password = to_str(password)?;
+        let plaintext = decrypt_bytes(data, password, &Options::new())?;
+        write_buffer(plaintext.to_vec(), output, output_length)
+    })
+}
+
+/// Zeroes and releases a buffer returned by this library. Null is ignored.
+///
+/// # Safety
+///
+/// `buffer` and `length` must come from one successful call, and be freed only once.
+#[no_mangle]
+pub unsafe extern "C" fn stic_buffer_free(buffer: *mut u8, length: usize) {
+    if !buffer.is_null() {
+        let buffer = Box::from_raw(ptr::slice_from_raw_parts_mut(buffer, length));
+        drop(zeroize::Zeroizing::new(buffer));
+    }
+}
+
+/// Runs `call`, recording its error for `stic_last_error`.
+fn status(call: impl FnOnce() -> Result<(), Box<dyn Error>>) -> SticStatus {
+    // A panic must not unwind into the caller's frames, which aborts the whole process
+    let result = panic::catch_unwind(AssertUnwindSafe(call)).unwrap_or_else(|panic| {
+        let reason = match panic.downcast_ref::<&str>() {
+            Some(reason) => reason.to_string(),
+            None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+        };
+        Err(Box::from(format!("Internal error: {reason}")))
+    });
+    let (status, message) = match result {
+        Ok(()) => (SticStatus::Ok, None),
+        Err(e) => {
+            let status = match e.downcast_ref() {
+                Some(SticError::Cancelled) => SticStatus::Cancelled,
+                Some(SticError::WrongPassword) => SticStatus::WrongPassword,
+                Some(SticError::CorruptHeader { .. }) => SticStatus::CorruptHeader,
+                Some(SticError::CorruptPayload { .. }) => SticStatus::CorruptPayload,
+                Some(SticError::TruncatedPayload { .. }) => SticStatus::TruncatedPayload,
+                None if e.is::<InvalidArgument>() => SticStatus::InvalidArgument,
+                None => SticStatus::Error,
+            };
+            let message = e.to_string().replace('\0', "");
+            (status, Some(CString::new(message).unwrap()))
+        }
+    };
+    LAST_ERROR.with(|error| *error.borrow_mut() = message);
+    status
+}
+
+#[derive(Debug)]
+struct InvalidArgument(&'static str);
+
+impl std::fmt::Display for InvalidArgument {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for InvalidArgument {}
+
+unsafe fn to_str<'a>(string: *const c_char) -> Result<&'a str, Box<dyn Error>> {
+    if string.is_null() {
+        return Err(Box::new(InvalidArgument("Unexpected null string")));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| Box::from(InvalidArgument("Strings must be valid UTF-8")))
+}
+
+unsafe fn to_slice<'a>(data: *const u8, length: usize) -> Result<&'a [u8], Box<dyn Error>> {
+    if length == 0 {
+        return Ok(&[]);
+    }
+    if data.is_null() {
+        return Err(Box::new(InvalidArgument("Unexpected null buffer")));
+    }
+    Ok(slice::from_raw_parts(data, length))
+}
+
+unsafe fn write_buffer(
+    buffer: Vec<u8>,
+    output: *mut *mut u8,
+    output_length: *mut usize,
+) -> Result<(), Box<dyn Error>> {
+    if output.is_null() || output_length.is_null() {
+        return Err(Box::new(InvalidArgument("Unexpected null output")));
+    }
+    let buffer = buffer.into_boxed_slice();
+    *output_length = buffer.len();
+    *output = Box::into_raw(buffer) as *mut u8;
+    Ok(())
+}
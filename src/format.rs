@@ -0,0 +1,294 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+//! Layout of the header at the start of every encrypted file.
+//!
+//! ```text
+//! VERSION_1:  token || stream IV
+//! VERSION_2:  token || algorithm id || compression id || padding id || nonce prefix
+//! VERSION_3:  VERSION_2 header || key commitment
+//! VERSION_4:  VERSION_3 header, with the KDF id in the token
+//! token:      version || KDF id (VERSION_4 onwards) || salt || IV || wrapped stream key || HMAC
+//! ```
+
+use std::{
+    error::Error,
+    fmt,
+    io::{Read, Seek, SeekFrom},
+};
+
+use crate::{
+    constants,
+    core::{kdf_from_id, Algorithm},
+    error::SticError,
+    utils::{Compression, Padding},
+};
+
+/// File format version, the first bytes of every file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    /// A single AES-256-GCM stream over the whole tar.
+    V1,
+    /// Segmented payload with selectable cipher, compression and padding.
+    V2,
+    /// Adds the key commitment and authenticates the whole header.
+    V3,
+    /// Records the KDF, so that keyfiles skip the password stretching.
+    V4,
+}
+
+impl Version {
+    pub const CURRENT: Version = Version::V4;
+
+    pub fn bytes(self) -> &'static [u8] {
+        match self {
+            Version::V1 => constants::VERSION_1,
+            Version::V2 => constants::VERSION_2,
+            Version::V3 => constants::VERSION_3,
+            Version::V4 => constants::VERSION,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        [Version::V1, Version::V2, Version::V3, Version::V4]
+            .into_iter()
+            .find(|version| version.bytes() == bytes)
+            .ok_or_else(|| Box::from("Invalid file version"))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let number = match self {
+            Version::V1 => 1,
+            Version::V2 => 2,
+            Version::V3 => 3,
+            Version::V4 => 4,
+        };
+        write!(f, "{number}")
+    }
+}
+
+/// The stream key wrapped under the password, authenticated by an HMAC over the hash of
+/// everything before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub version: Version,
+    /// Id of the `PasswordKdf` that derives the wrapping key, always PBKDF2 before `VERSION_4`.
+    pub kdf: u8,
+    pub salt: [u8; constants::TOKEN_SALT_SIZE],
+    pub iv: [u8; constants::TOKEN_IV_SIZE],
+    pub wrapped_key: [u8; constants::TOKEN_CIPHERTEXT_SIZE],
+    pub hmac: [u8; constants::TOKEN_HMAC_SIZE],
+}
+
+impl Token {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut fields = Fields::new(bytes);
+        let token = Token::read(&mut fields)?;
+        fields.finish()?;
+        Ok(token)
+    }
+
+    fn read(fields: &mut Fields) -> Result<Self, Box<dyn Error>> {
+        let version = fields.version()?;
+        let kdf = match version {
+            Version::V4 => fields.id(kdf_from_id)?,
+            _ => constants::KDF_PBKDF2_HMAC_SHA3_512,
+        };
+        Ok(Token {
+            version,
+            kdf,
+            salt: fields.array()?,
+            iv: fields.array()?,
+            wrapped_key: fields.array()?,
+            hmac: fields.array()?,
+        })
+    }
+
+    /// The bytes covered by the HMAC.
+    pub fn signed(&self) -> Vec<u8> {
+        let kdf: &[u8] = match self.version {
+            Version::V4 => &[self.kdf],
+            _ => &[],
+        };
+        [
+            self.version.bytes(),
+            kdf,
+            &self.salt,
+            &self.iv,
+            &self.wrapped_key,
+        ]
+        .concat()
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        [&self.signed()[..], &self.hmac].concat()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub token: Token,
+    pub algorithm: Algorithm,
+    pub compression: Compression,
+    pub padding: Padding,
+    /// The IV of the single stream in `VERSION_1`, the segment nonce prefix afterwards.
+    pub nonce: Vec<u8>,
+    /// `VERSION_3` onwards.
+    pub commitment: Option<[u8; constants::KEY_COMMITMENT_SIZE]>,
+}
+
+impl Header {
+    pub fn version(&self) -> Version {
+        self.token.version
+    }
+
+    /// Parses the header at the start of `bytes`, which may go on with the payload.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut fields = Fields::new(bytes);
+        let token = Token::read(&mut fields)?;
+
+        if token.version == Version::V1 {
+            return Ok(Header {
+                token,
+                algorithm: Algorithm::Aes256Gcm,
+                compression: Compression::None,
+                padding: Padding::None,
+                nonce: fields.take(constants::STREAM_IV_SIZE)?.to_vec(),
+                commitment: None,
+            });
+        }
+
+        let algorithm = fields.id(Algorithm::from_id)?;
+        let compression = fields.id(Compression::from_id)?;
+        let padding = fields.id(Padding::from_id)?;
+        let nonce = fields.take(algorithm.nonce_prefix_size())?.to_vec();
+        let commitment = match token.version {
+            Version::V3 | Version::V4 => Some(fields.array()?),
+            _ => None,
+        };
+
+        Ok(Header {
+            token,
+            algorithm,
+            compression,
+            padding,
+            nonce,
+            commitment,
+        })
+    }
+
+    /// Reads the header at the start of `reader`, leaving it positioned at the payload.
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mut bytes = Vec::with_capacity(constants::MAX_HEADER_SIZE);
+        reader
+            .by_ref()
+            .take(constants::MAX_HEADER_SIZE as u64)
+            .read_to_end(&mut bytes)?;
+
+        let header = Header::parse(&bytes)?;
+        reader.seek(SeekFrom::Current(header.size() as i64 - bytes.len() as i64))?;
+        Ok(header)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = self.token.serialize();
+        if self.version() != Version::V1 {
+            bytes.extend_from_slice(&self.parameters());
+        }
+        bytes.extend_from_slice(&self.nonce);
+        if let Some(commitment) = &self.commitment {
+            bytes.extend_from_slice(commitment);
+        }
+        bytes
+    }
+
+    pub fn size(&self) -> usize {
+        self.serialize().len()
+    }
+
+    /// Additional data authenticated with the payload.
+    pub fn aad(&self) -> Vec<u8> {
+        match self.version() {
+            Version::V1 => [constants::VERSION_1, &self.nonce].concat(),
+            Version::V2 => [constants::VERSION_2, &self.parameters(), &self.nonce].concat(),
+            Version::V3 | Version::V4 => self.serialize(),
+        }
+    }
+
+    fn parameters(&self) -> [u8; 3] {
+        [
+            self.algorithm.id(),
+            self.compression.id(),
+            self.padding.id(),
+        ]
+    }
+}
+
+/// Splits fields off the front of a header, failing instead of reading past its end.
+struct Fields<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Fields { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let end = self.offset + size;
+        let field = self
+            .bytes
+            .get(self.offset..end)
+            .ok_or_else(|| corrupt(self.bytes.len(), "the file ends inside the header"))?;
+        self.offset = end;
+        Ok(field)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Box<dyn Error>> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn version(&mut self) -> Result<Version, Box<dyn Error>> {
+        let offset = self.offset;
+        let bytes = self.take(constants::VERSION.len())?;
+        Version::from_bytes(bytes).map_err(|e| corrupt(offset, e))
+    }
+
+    /// Reads a one-byte id, reporting unknown ones at their offset.
+    fn id<T>(&mut self, from_id: fn(u8) -> Result<T, Box<dyn Error>>) -> Result<T, Box<dyn Error>> {
+        let offset = self.offset;
+        let id = self.take(1)?[0];
+        from_id(id).map_err(|e| corrupt(offset, e))
+    }
+
+    fn finish(&self) -> Result<(), Box<dyn Error>> {
+        if self.offset != self.bytes.len() {
+            return Err(corrupt(self.offset, "unexpected trailing bytes"));
+        }
+        Ok(())
+    }
+}
+
+fn corrupt(offset: usize, reason: impl ToString) -> Box<dyn Error> {
+    Box::new(SticError::CorruptHeader {
+        offset: offset as u64,
+        reason: reason.to_string(),
+    })
+}
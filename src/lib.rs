@@ -15,33 +15,105 @@ If not, see <https://www.gnu.org/licenses/>.
 Copyright (C) 2022 Salvador Bravo Garnica
 */
 
+mod backend;
 mod constants;
 mod core;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod format;
 mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-use std::{error::Error, path::PathBuf};
+use std::{
+    error::Error,
+    fs::File,
+    io::{stderr, stdin, stdout, IsTerminal},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
 pub use crate::{
     constants::BUILD,
-    core::{decrypt_file, encrypt_file},
+    core::{
+        bench, decrypt_bytes, decrypt_file, decrypt_file_into, decrypt_file_with, decrypt_stream,
+        encrypt_bytes, encrypt_file, encrypt_file_with, encrypt_stream, git_clean, git_smudge,
+        read_keyfile, Aead, Algorithm, BenchResult, Decryptor, Encryptor, Options, PasswordKdf,
+        Registry,
+    },
+    error::SticError,
+    format::{Header, Token, Version},
     utils::{
-        get_decrypted_path, get_encrypted_path, read_password, sanitize_path, validate_decryption,
-        validate_encryption,
+        clear_keys, get_anonymized_path, get_decrypted_path, get_encrypted_path, read_header,
+        read_password, sanitize_path, validate_decryption, validate_encryption, CancelToken,
+        Compression, Padding, Phase, Preserve, Progress, ProgressBar, SecureBuffer, Symlinks,
     },
 };
 
-use zeroize::Zeroizing;
+#[cfg(unix)]
+pub use crate::core::{agent_add, agent_identities, agent_lock, agent_socket, run_agent};
+
+#[cfg(feature = "async")]
+pub use crate::core::{
+    decrypt_file_async, decrypt_file_into_async, encrypt_file_async, AsyncDecryptReader,
+    AsyncEncryptWriter,
+};
+
+#[cfg(unix)]
+use crate::core::agent_unwrap;
+use crate::{
+    core::unwrap_stream_key,
+    utils::{cached_key, format_bytes},
+};
 
 pub struct Config {
     input_path: PathBuf,
     action: Action,
     output_path: PathBuf,
-    password: Zeroizing<String>,
+    password: SecureBuffer,
+    threads: usize,
+    algorithm: Algorithm,
+    compression: Compression,
+    compression_level: Option<u32>,
+    padding: Padding,
+    preserve: Preserve,
+    symlinks: Symlinks,
+    armor: bool,
+    anonymize_name: bool,
+    keyfile: PathBuf,
+    deterministic: bool,
+    key_cache: Option<Duration>,
+    agent: bool,
+    agent_timeout: Option<Duration>,
+    /// Stream key of the file to decrypt, found without the password.
+    stream_key: Option<(Token, SecureBuffer)>,
+    cancel: CancelToken,
 }
 
 enum Action {
     Encrypt,
     Decrypt,
+    Info,
+    Bench,
+    GitFilter(GitFilter),
+    Agent(AgentCommand),
+}
+
+#[derive(Clone, Copy)]
+enum GitFilter {
+    Clean,
+    Smudge,
+    Textconv,
+}
+
+#[derive(Clone, Copy)]
+enum AgentCommand {
+    Start,
+    Add,
+    Lock,
+    Clear,
 }
 
 impl Config {
@@ -50,20 +122,88 @@ impl Config {
             input_path: PathBuf::new(),
             action: Action::Encrypt,
             output_path: PathBuf::new(),
-            password: Zeroizing::new(String::new()),
+            password: SecureBuffer::new(0),
+            threads: 0,
+            algorithm: Algorithm::default(),
+            compression: Compression::None,
+            compression_level: None,
+            padding: Padding::None,
+            preserve: Preserve::ALL,
+            symlinks: Symlinks::Preserve,
+            armor: false,
+            anonymize_name: false,
+            keyfile: PathBuf::new(),
+            deterministic: false,
+            key_cache: None,
+            agent: false,
+            agent_timeout: None,
+            stream_key: None,
+            cancel: CancelToken::new(),
         }
     }
 
+    /// Stops `run` once `cancel` is cancelled, typically from a signal handler set up by the
+    /// caller.
+    pub fn cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
     pub fn run(&self) -> Result<(), Box<dyn Error>> {
-        match &self.action {
+        let mut options = Options::new()
+            .cancel(self.cancel.clone())
+            .threads(self.threads)
+            .algorithm(self.algorithm)
+            .compression(self.compression)
+            .padding(self.padding)
+            .preserve(self.preserve)
+            .symlinks(self.symlinks)
+            .armor(self.armor);
+        if let Some(level) = self.compression_level {
+            options = options.compression_level(level);
+        }
+        if let Some(ttl) = self.key_cache {
+            options = options.key_cache(ttl);
+        }
+        if let Some((token, key)) = &self.stream_key {
+            options = options.stream_key(token.clone(), key.clone());
+        }
+
+        match self.action {
+            Action::Bench => return self.bench(&options),
+            Action::Info => return self.info(),
+            Action::GitFilter(filter) => return self.git_filter(filter, &options),
+            Action::Agent(command) => return self.agent(command, &options),
+            _ => {}
+        }
+
+        let progress_bar = if stderr().is_terminal() {
+            let progress_bar = Arc::new(ProgressBar::new());
+            options = options.progress(progress_bar.clone());
+            Some(progress_bar)
+        } else {
+            None
+        };
+
+        let password = std::str::from_utf8(&self.password)?;
+        let result = match &self.action {
             Action::Encrypt => {
-                encrypt_file(&self.input_path, &self.password, &self.output_path)?;
+                encrypt_file_with(&self.input_path, password, &self.output_path, &options)
             }
             Action::Decrypt => {
-                decrypt_file(&self.input_path, &self.password, &self.output_path)?;
+                decrypt_file_into(&self.input_path, password, &self.output_path, &options)
+                    .map(|_| ())
+            }
+            Action::Info | Action::Bench | Action::GitFilter(_) | Action::Agent(_) => {
+                unreachable!()
             }
+        };
+
+        if let Some(progress_bar) = progress_bar {
+            progress_bar.finish();
         }
-        Ok(())
+
+        result
     }
 
     pub fn parse() -> Result<Config, Box<dyn Error>> {
@@ -72,42 +212,303 @@ impl Config {
         let n = args.len();
         let name = &args[0];
 
-        return match n {
-            2 => {
-                let action = args[1].as_str();
-                match action {
-                    "-v" | "--version" => Err(Box::from(BUILD)),
-                    "-h" | "--help" => Err(Box::from(help(name))),
-                    _ => Err(Box::from(usage(name))),
-                }
+        if n == 2 {
+            match args[1].as_str() {
+                "-v" | "--version" => return Err(Box::from(BUILD)),
+                "-h" | "--help" => return Err(Box::from(help(name))),
+                _ => {}
             }
-            3 => {
-                let mut config = Config::new();
-                let action = args[1].as_str();
-                let path = sanitize_path(args[2].as_str())?;
-                match action {
-                    "-e" | "--encrypt" => {
-                        config.action = Action::Encrypt;
-                        validate_encryption(&path)?;
-                        config.input_path = path;
-                        config.output_path = get_encrypted_path(&config.input_path);
-                        config.password = read_password(true)?;
-                    }
-                    "-d" | "--decrypt" => {
-                        config.action = Action::Decrypt;
-                        validate_decryption(&path)?;
-                        config.input_path = path;
-                        config.output_path = get_decrypted_path(&config.input_path);
-                        config.password = read_password(false)?;
-                    }
-                    _ => {
-                        return Err(Box::from(usage(name)));
+        }
+
+        let mut config = Config::new();
+        let mut action = None;
+        let mut path = None;
+        let mut compress = false;
+
+        let mut args = args[1..].iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-e" | "--encrypt" if action.is_none() => action = Some(Action::Encrypt),
+                "-d" | "--decrypt" if action.is_none() => action = Some(Action::Decrypt),
+                "bench" if action.is_none() && path.is_none() => action = Some(Action::Bench),
+                "info" if action.is_none() && path.is_none() => action = Some(Action::Info),
+                "git-filter" if action.is_none() && path.is_none() => {
+                    let filter = match args.next().map(String::as_str) {
+                        Some("clean") => GitFilter::Clean,
+                        Some("smudge") => GitFilter::Smudge,
+                        Some("textconv") => GitFilter::Textconv,
+                        _ => return Err(Box::from(usage(name))),
+                    };
+                    action = Some(Action::GitFilter(filter));
+                }
+                "agent" if action.is_none() && path.is_none() => {
+                    let command = match args.next().map(String::as_str) {
+                        Some("start") => AgentCommand::Start,
+                        Some("add") => AgentCommand::Add,
+                        Some("lock") => AgentCommand::Lock,
+                        Some("clear") => AgentCommand::Clear,
+                        _ => return Err(Box::from(usage(name))),
+                    };
+                    action = Some(Action::Agent(command));
+                }
+                "--agent" => {
+                    config.agent = true;
+                }
+                "--timeout" => {
+                    let timeout = args.next().ok_or_else(|| usage(name))?;
+                    let timeout = timeout
+                        .parse()
+                        .map_err(|_| "Agent timeout must be a number of seconds")?;
+                    config.agent_timeout = Some(Duration::from_secs(timeout));
+                }
+                "--cache-key" => {
+                    config.key_cache = Some(Duration::from_secs(constants::KEY_CACHE_TTL));
+                }
+                _ if arg.starts_with("--cache-key=") => {
+                    let ttl = arg["--cache-key=".len()..]
+                        .parse()
+                        .map_err(|_| "Key cache time must be a number of seconds")?;
+                    config.key_cache = Some(Duration::from_secs(ttl));
+                }
+                "--keyfile" => {
+                    let keyfile = args.next().ok_or_else(|| usage(name))?;
+                    config.keyfile = PathBuf::from(keyfile);
+                }
+                "--deterministic" => {
+                    config.deterministic = true;
+                }
+                "-t" | "--threads" => {
+                    let threads = args.next().ok_or_else(|| usage(name))?;
+                    config.threads = match threads.parse::<usize>() {
+                        Ok(threads) if threads > 0 => threads,
+                        _ => return Err(Box::from("Number of threads must be a positive integer")),
+                    };
+                }
+                "-c" | "--cipher" => {
+                    let algorithm = args.next().ok_or_else(|| usage(name))?;
+                    config.algorithm = algorithm.parse()?;
+                }
+                "--compress" => {
+                    compress = true;
+                }
+                _ if arg.starts_with("--compress=") => {
+                    let level = &arg["--compress=".len()..];
+                    compress = true;
+                    config.compression_level = Some(
+                        level
+                            .parse()
+                            .map_err(|_| "Compression level must be a number")?,
+                    );
+                }
+                "--compressor" => {
+                    let compression = args.next().ok_or_else(|| usage(name))?;
+                    compress = true;
+                    config.compression = compression.parse()?;
+                }
+                "--no-preserve" => {
+                    let fields = args.next().ok_or_else(|| usage(name))?;
+                    for field in fields.split(',') {
+                        match field {
+                            "mode" => config.preserve.mode = false,
+                            "times" => config.preserve.times = false,
+                            "owner" => config.preserve.ownership = false,
+                            "xattrs" => config.preserve.xattrs = false,
+                            "all" => config.preserve = Preserve::NONE,
+                            _ => return Err(Box::from(format!("Unknown metadata {field}"))),
+                        }
                     }
                 }
-                return Ok(config);
+                "--follow-symlinks" => {
+                    config.symlinks = Symlinks::Follow;
+                }
+                "--preserve-symlinks" => {
+                    config.symlinks = Symlinks::Preserve;
+                }
+                "--armor" => {
+                    config.armor = true;
+                }
+                "--anonymize-name" => {
+                    config.anonymize_name = true;
+                }
+                "--pad" => {
+                    config.padding = Padding::Padme;
+                }
+                _ if arg.starts_with("--pad=") => {
+                    config.padding = arg["--pad=".len()..].parse()?;
+                }
+                _ if path.is_none() && !arg.starts_with('-') => path = Some(arg.as_str()),
+                _ => return Err(Box::from(usage(name))),
+            }
+        }
+
+        if compress && config.compression == Compression::None {
+            config.compression = Compression::Zstd;
+        }
+
+        match (action, path) {
+            (Some(Action::Bench), None) => {
+                config.action = Action::Bench;
+            }
+            (Some(Action::Encrypt), Some(path)) => {
+                let path = sanitize_path(path)?;
+                config.action = Action::Encrypt;
+                validate_encryption(&path)?;
+                config.input_path = path;
+                config.output_path = if config.anonymize_name {
+                    get_anonymized_path(&config.input_path)
+                } else {
+                    get_encrypted_path(&config.input_path)
+                };
+                config.password = read_password(true)?;
             }
-            _ => Err(Box::from(usage(name))),
+            (Some(Action::Decrypt), Some(path)) => {
+                let path = sanitize_path(path)?;
+                config.action = Action::Decrypt;
+                validate_decryption(&path)?;
+                config.input_path = path;
+                config.output_path = config.input_path.parent().unwrap().to_path_buf();
+                // A cached key opens the file whatever the password. It is unwrapped here, since
+                // the cache entry may expire before `run` needs it.
+                let token = read_header(&config.input_path)?.0.token;
+                if config.key_cache.is_some() {
+                    config.stream_key = cached_key(&token.salt)
+                        .and_then(|key| unwrap_stream_key(&key, &token).ok().flatten())
+                        .map(|key| (token.clone(), key));
+                }
+                // The agent is only asked when the user opted in, so that nothing else listening
+                // on the default socket gets to see which files are opened.
+                #[cfg(unix)]
+                if config.stream_key.is_none()
+                    && (config.agent || std::env::var_os(constants::AGENT_SOCKET_ENV).is_some())
+                {
+                    config.stream_key = agent_unwrap(&agent_socket(), &token)
+                        .ok()
+                        .flatten()
+                        .map(|key| (token.clone(), key));
+                }
+                #[cfg(not(unix))]
+                if config.agent {
+                    return Err(Box::from("The agent needs Unix sockets"));
+                }
+                if config.stream_key.is_none() {
+                    config.password = read_password(false)?;
+                }
+            }
+            (Some(Action::GitFilter(filter)), path) => {
+                if config.keyfile.as_os_str().is_empty() {
+                    return Err(Box::from("git-filter needs a --keyfile"));
+                }
+                match (filter, path) {
+                    (GitFilter::Textconv, Some(path)) => config.input_path = PathBuf::from(path),
+                    (GitFilter::Clean | GitFilter::Smudge, None) => {}
+                    _ => return Err(Box::from(usage(name))),
+                }
+                config.action = Action::GitFilter(filter);
+            }
+            (Some(Action::Agent(command)), None) => {
+                if matches!(command, AgentCommand::Add) && config.keyfile.as_os_str().is_empty() {
+                    config.password = read_password(false)?;
+                }
+                config.action = Action::Agent(command);
+            }
+            (Some(Action::Info), Some(path)) => {
+                let path = PathBuf::from(path);
+                validate_decryption(&path)?;
+                config.action = Action::Info;
+                config.input_path = path;
+            }
+            _ => return Err(Box::from(usage(name))),
+        }
+
+        Ok(config)
+    }
+
+    fn info(&self) -> Result<(), Box<dyn Error>> {
+        let (header, size) = read_header(&self.input_path)?;
+        let commitment = if header.commitment.is_some() {
+            "yes"
+        } else {
+            "no"
         };
+        println!("version         {}", header.version());
+        println!("cipher          {}", header.algorithm);
+        println!("compression     {}", header.compression);
+        println!("padding         {}", header.padding);
+        println!("key commitment  {commitment}");
+        println!("header size     {} bytes", header.size());
+        println!("payload size    {} bytes", size - header.size() as u64);
+        Ok(())
+    }
+
+    /// Runs as a git filter driver, between stdin (or the file git names for `textconv`) and
+    /// stdout.
+    fn git_filter(&self, filter: GitFilter, options: &Options) -> Result<(), Box<dyn Error>> {
+        let keyfile = read_keyfile(&self.keyfile)?;
+        let mut output = stdout().lock();
+        match filter {
+            GitFilter::Clean => git_clean(
+                &mut stdin().lock(),
+                &mut output,
+                &keyfile,
+                self.deterministic,
+                options,
+            ),
+            GitFilter::Smudge => git_smudge(&mut stdin().lock(), &mut output, &keyfile, options),
+            GitFilter::Textconv => git_smudge(
+                &mut File::open(&self.input_path)?,
+                &mut output,
+                &keyfile,
+                options,
+            ),
+        }
+    }
+
+    #[cfg(unix)]
+    fn agent(&self, command: AgentCommand, options: &Options) -> Result<(), Box<dyn Error>> {
+        let socket = agent_socket();
+        match command {
+            AgentCommand::Start => {
+                eprintln!("agent listening on {}", socket.display());
+                run_agent(&socket, options)
+            }
+            AgentCommand::Add if self.keyfile.as_os_str().is_empty() => {
+                agent_add(&socket, &self.password, self.agent_timeout)
+            }
+            AgentCommand::Add => {
+                agent_add(&socket, &read_keyfile(&self.keyfile)?, self.agent_timeout)
+            }
+            AgentCommand::Lock => agent_lock(&socket),
+            AgentCommand::Clear => Ok(clear_keys()?),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn agent(&self, command: AgentCommand, _options: &Options) -> Result<(), Box<dyn Error>> {
+        match command {
+            AgentCommand::Clear => Ok(clear_keys()?),
+            _ => Err(Box::from("The agent needs Unix sockets")),
+        }
+    }
+
+    fn bench(&self, options: &Options) -> Result<(), Box<dyn Error>> {
+        eprintln!(
+            "encrypting {} in memory...",
+            format_bytes(constants::BENCH_SIZE as f64).trim_start()
+        );
+        println!(
+            "{:<18}  threads  {:>13}  {:>13}",
+            "algorithm", "encrypt", "decrypt"
+        );
+        for result in bench(options)? {
+            println!(
+                "{:<18}  {:>7}  {}/s  {}/s",
+                result.algorithm.to_string(),
+                result.threads,
+                format_bytes(result.encrypt),
+                format_bytes(result.decrypt)
+            );
+        }
+        Ok(())
     }
 }
 
@@ -115,7 +516,13 @@ fn usage(name: &str) -> String {
     format!(
         "usage:
     {name} [-v|-h]
-    {name} (-e|-d) path"
+    {name} [options] (-e|-d) path
+    {name} info path
+    {name} [-t threads] bench
+    {name} git-filter (clean|smudge) --keyfile file [--deterministic]
+    {name} git-filter textconv --keyfile file path
+    {name} agent (start|lock|clear)
+    {name} agent add [--keyfile file] [--timeout s]"
     )
 }
 
@@ -129,12 +536,58 @@ flags:
     -v, --version     show release version and exit
     -h, --help        show this help message and exit
 
+options:
+    -t, --threads n   encrypt/decrypt using n threads (default: one per core)
+    -c, --cipher c    encrypt with aes-256-gcm (default) or xchacha20-poly1305
+    --compress[=level]
+                      compress before encrypting, optionally at the given level
+    --compressor c    compress with zstd (default), gzip or xz
+    --pad[=scheme]    hide the exact size with padme (default) or bucket padding
+    --no-preserve m   do not keep mode, times, owner, xattrs or all (comma separated)
+    --follow-symlinks encrypt what symlinks point to instead of the links
+    --preserve-symlinks
+                      encrypt symlinks inside path as links (default); a symlink
+                      given as path is always followed
+    --armor           write the encrypted file as base64 text with BEGIN/END markers
+    --anonymize-name  give the encrypted file a random name; decryption restores the original
+    --cache-key[=s]   keep the password-derived key in the session keyring for s seconds
+                      (default: 600), so decrypting the file again skips the password
+    --keyfile file    key for git-filter or agent add, at least 32 random bytes; git-filter
+                      wraps keys under it without password stretching
+    --agent           ask the running agent for the key before prompting for the password
+                      (implied when $STIC_AGENT_SOCK is set)
+    --timeout s       make the agent forget the added password or keyfile after s seconds
+    --deterministic   make git-filter clean encrypt unchanged content to the same bytes, which
+                      shows which versions of a file are identical
+
 actions:
     -e, --encrypt     encrypt path
     -d, --decrypt     decrypt path
+    info              show the header of encrypted path
+    bench             measure encryption throughput per thread count
+    git-filter m      encrypt (clean) or decrypt (smudge) stdin to stdout for a git filter, or
+                      decrypt path to stdout for a diff textconv; set it up with
+                        git config filter.stic.clean '{name} git-filter clean --keyfile k'
+                        git config filter.stic.smudge '{name} git-filter smudge --keyfile k'
+                        git config diff.stic.textconv '{name} git-filter textconv --keyfile k'
+                      and `secret/** filter=stic diff=stic` in .gitattributes
+    agent start       hold passwords and keyfiles for the session on a Unix socket
+                      ($STIC_AGENT_SOCK, default: $XDG_RUNTIME_DIR/stic-agent-<uid>.sock,
+                      or $TMPDIR/stic-<uid>/agent.sock); decrypt with --agent to use it
+    agent add         unlock the password (prompted) or --keyfile in the running agent
+    agent lock        make the agent forget every password, keyfile and key
+    agent clear       drop the keys cached with --cache-key
 
 path                  path to a file
 
+exit status:
+    0                 success
+    1                 any other error
+    2                 wrong password
+    3                 corrupt header
+    4                 corrupt or truncated payload
+    130               cancelled
+
 Copyright (C) 2022 Salvador Bravo Garnica
 
 This program is free software: you can redistribute it and/or modify
@@ -0,0 +1,154 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::io::{self, BufRead, Read, Seek, Write};
+
+use crate::constants;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Whether `reader` starts with the armor marker. The position is restored.
+pub fn is_armored<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let start = reader.stream_position()?;
+    let mut marker = Vec::with_capacity(constants::ARMOR_BEGIN.len());
+    reader
+        .by_ref()
+        .take(constants::ARMOR_BEGIN.len() as u64)
+        .read_to_end(&mut marker)?;
+    reader.seek(io::SeekFrom::Start(start))?;
+    Ok(marker == constants::ARMOR_BEGIN.as_bytes())
+}
+
+/// Writes the binary container as base64 lines between BEGIN and END markers.
+pub struct ArmorWriter<W: Write> {
+    inner: W,
+    line: Vec<u8>,
+}
+
+impl<W: Write> ArmorWriter<W> {
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        writeln!(inner, "{}", constants::ARMOR_BEGIN)?;
+        Ok(Self {
+            inner,
+            line: Vec::with_capacity(constants::ARMOR_LINE_BYTES),
+        })
+    }
+
+    /// Writes the last, possibly short, line and the END marker, returning the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.line.is_empty() {
+            self.write_line()?;
+        }
+        writeln!(self.inner, "{}", constants::ARMOR_END)?;
+        Ok(self.inner)
+    }
+
+    fn write_line(&mut self) -> io::Result<()> {
+        writeln!(self.inner, "{}", STANDARD.encode(&self.line))?;
+        self.line.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ArmorWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut data = buf;
+        while !data.is_empty() {
+            let taken = (constants::ARMOR_LINE_BYTES - self.line.len()).min(data.len());
+            self.line.extend_from_slice(&data[..taken]);
+            data = &data[taken..];
+
+            if self.line.len() == constants::ARMOR_LINE_BYTES {
+                self.write_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads the binary container back out of its armored form, failing on anything between the
+/// markers that is not base64 and on a missing END marker.
+pub struct ArmorReader<R: BufRead> {
+    inner: R,
+    started: bool,
+    ended: bool,
+    decoded: Vec<u8>,
+    position: usize,
+}
+
+impl<R: BufRead> ArmorReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            started: false,
+            ended: false,
+            decoded: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Decodes the next line into `decoded`, returning false once the END marker is reached.
+    fn next_line(&mut self) -> io::Result<bool> {
+        let mut line = String::new();
+        if self.inner.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Armored file is missing its END marker",
+            ));
+        }
+        let line = line.trim_end();
+
+        if !self.started {
+            if line != constants::ARMOR_BEGIN {
+                return Err(invalid_armor());
+            }
+            self.started = true;
+            return Ok(true);
+        }
+        if line == constants::ARMOR_END {
+            self.ended = true;
+            return Ok(false);
+        }
+
+        self.decoded = STANDARD.decode(line).map_err(|_| invalid_armor())?;
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Read for ArmorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.decoded.len() {
+            if self.ended || !self.next_line()? {
+                return Ok(0);
+            }
+        }
+
+        let count = (self.decoded.len() - self.position).min(buf.len());
+        buf[..count].copy_from_slice(&self.decoded[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+fn invalid_armor() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Invalid armored file")
+}
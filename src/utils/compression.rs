@@ -0,0 +1,289 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Read, Write},
+    ops::RangeInclusive,
+    str::FromStr,
+};
+
+use crate::constants;
+
+use flate2::{
+    read::GzDecoder,
+    write::{self as gz_write, GzEncoder},
+};
+#[cfg(not(target_family = "wasm"))]
+use xz2::{
+    read::XzDecoder,
+    write::{self as xz_write, XzEncoder},
+};
+
+/// Compression applied to the tar before encryption, stored in the header by its `id`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+    Gzip,
+    Xz,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 4] = [
+        Compression::None,
+        Compression::Zstd,
+        Compression::Gzip,
+        Compression::Xz,
+    ];
+
+    pub fn id(self) -> u8 {
+        match self {
+            Compression::None => constants::COMPRESSION_NONE,
+            Compression::Zstd => constants::COMPRESSION_ZSTD,
+            Compression::Gzip => constants::COMPRESSION_GZIP,
+            Compression::Xz => constants::COMPRESSION_XZ,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, Box<dyn Error>> {
+        Compression::ALL
+            .into_iter()
+            .find(|compression| compression.id() == id)
+            .ok_or_else(|| Box::from(format!("Unknown compression id {id}")))
+    }
+
+    pub fn default_level(self) -> u32 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => constants::ZSTD_DEFAULT_LEVEL,
+            Compression::Gzip | Compression::Xz => constants::GZIP_XZ_DEFAULT_LEVEL,
+        }
+    }
+
+    pub fn levels(self) -> RangeInclusive<u32> {
+        match self {
+            Compression::None => 0..=0,
+            Compression::Zstd => 1..=constants::ZSTD_MAX_LEVEL,
+            Compression::Gzip | Compression::Xz => 0..=constants::GZIP_XZ_MAX_LEVEL,
+        }
+    }
+
+    pub fn encoder<W: Write>(self, writer: W, level: u32) -> Result<Encoder<W>, Box<dyn Error>> {
+        let levels = self.levels();
+        if !levels.contains(&level) {
+            return Err(Box::from(format!(
+                "{self} compression level must be between {} and {}",
+                levels.start(),
+                levels.end()
+            )));
+        }
+
+        Ok(match self {
+            Compression::None => Encoder::None(writer),
+            #[cfg(not(target_family = "wasm"))]
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(writer, level as i32)?),
+            Compression::Gzip => {
+                Encoder::Gzip(GzEncoder::new(writer, flate2::Compression::new(level)))
+            }
+            #[cfg(not(target_family = "wasm"))]
+            Compression::Xz => Encoder::Xz(XzEncoder::new(writer, level)),
+            #[cfg(target_family = "wasm")]
+            Compression::Zstd | Compression::Xz => return Err(self.unavailable()),
+        })
+    }
+
+    pub fn decoder<'a, R: Read + 'a>(
+        self,
+        reader: R,
+    ) -> Result<Box<dyn Read + 'a>, Box<dyn Error>> {
+        Ok(match self {
+            Compression::None => Box::new(reader),
+            #[cfg(not(target_family = "wasm"))]
+            Compression::Zstd => Box::new(zstd::Decoder::new(reader)?),
+            Compression::Gzip => Box::new(GzDecoder::new(reader)),
+            #[cfg(not(target_family = "wasm"))]
+            Compression::Xz => Box::new(XzDecoder::new(reader)),
+            #[cfg(target_family = "wasm")]
+            Compression::Zstd | Compression::Xz => return Err(self.unavailable()),
+        })
+    }
+
+    /// Like `decoder`, for compressed data that is pushed rather than pulled.
+    pub fn write_decoder<W: Write>(self, writer: W) -> Result<Decoder<W>, Box<dyn Error>> {
+        Ok(match self {
+            Compression::None => Decoder::None(writer),
+            #[cfg(not(target_family = "wasm"))]
+            Compression::Zstd => Decoder::Zstd(zstd::stream::write::Decoder::new(writer)?),
+            Compression::Gzip => Decoder::Gzip(gz_write::GzDecoder::new(writer)),
+            #[cfg(not(target_family = "wasm"))]
+            Compression::Xz => Decoder::Xz(xz_write::XzDecoder::new(writer)),
+            #[cfg(target_family = "wasm")]
+            Compression::Zstd | Compression::Xz => return Err(self.unavailable()),
+        })
+    }
+
+    /// zstd and xz are C libraries, which are not built for the browser.
+    #[cfg(target_family = "wasm")]
+    fn unavailable(self) -> Box<dyn Error> {
+        Box::from(format!("{self} compression is not available in this build"))
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+            Compression::Gzip => "gzip",
+            Compression::Xz => "xz",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Box<dyn Error>;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Compression::ALL
+            .into_iter()
+            .find(|compression| compression.to_string() == name)
+            .ok_or_else(|| Box::from(format!("Unknown compression {name}")))
+    }
+}
+
+pub enum Encoder<W: Write> {
+    None(W),
+    #[cfg(not(target_family = "wasm"))]
+    Zstd(zstd::Encoder<'static, W>),
+    Gzip(GzEncoder<W>),
+    #[cfg(not(target_family = "wasm"))]
+    Xz(XzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Writes any buffered output and the stream trailer, returning the inner writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::None(writer) => Ok(writer),
+            #[cfg(not(target_family = "wasm"))]
+            Encoder::Zstd(encoder) => encoder.finish(),
+            Encoder::Gzip(encoder) => encoder.finish(),
+            #[cfg(not(target_family = "wasm"))]
+            Encoder::Xz(encoder) => encoder.finish(),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        match self {
+            Encoder::None(writer) => writer,
+            #[cfg(not(target_family = "wasm"))]
+            Encoder::Zstd(encoder) => encoder.get_mut(),
+            Encoder::Gzip(encoder) => encoder.get_mut(),
+            #[cfg(not(target_family = "wasm"))]
+            Encoder::Xz(encoder) => encoder.get_mut(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::None(writer) => writer.write(buf),
+            #[cfg(not(target_family = "wasm"))]
+            Encoder::Zstd(encoder) => encoder.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            #[cfg(not(target_family = "wasm"))]
+            Encoder::Xz(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::None(writer) => writer.flush(),
+            #[cfg(not(target_family = "wasm"))]
+            Encoder::Zstd(encoder) => encoder.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            #[cfg(not(target_family = "wasm"))]
+            Encoder::Xz(encoder) => encoder.flush(),
+        }
+    }
+}
+
+pub enum Decoder<W: Write> {
+    None(W),
+    #[cfg(not(target_family = "wasm"))]
+    Zstd(zstd::stream::write::Decoder<'static, W>),
+    Gzip(gz_write::GzDecoder<W>),
+    #[cfg(not(target_family = "wasm"))]
+    Xz(xz_write::XzDecoder<W>),
+}
+
+impl<W: Write> Decoder<W> {
+    /// Writes any buffered output, failing if the compressed stream is incomplete.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Decoder::None(writer) => Ok(writer),
+            #[cfg(not(target_family = "wasm"))]
+            Decoder::Zstd(mut decoder) => {
+                decoder.flush()?;
+                Ok(decoder.into_inner())
+            }
+            Decoder::Gzip(decoder) => decoder.finish(),
+            #[cfg(not(target_family = "wasm"))]
+            Decoder::Xz(mut decoder) => decoder.finish(),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        match self {
+            Decoder::None(writer) => writer,
+            #[cfg(not(target_family = "wasm"))]
+            Decoder::Zstd(decoder) => decoder.get_mut(),
+            Decoder::Gzip(decoder) => decoder.get_mut(),
+            #[cfg(not(target_family = "wasm"))]
+            Decoder::Xz(decoder) => decoder.get_mut(),
+        }
+    }
+}
+
+impl<W: Write> Write for Decoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Decoder::None(writer) => writer.write(buf),
+            #[cfg(not(target_family = "wasm"))]
+            Decoder::Zstd(decoder) => decoder.write(buf),
+            Decoder::Gzip(decoder) => decoder.write(buf),
+            #[cfg(not(target_family = "wasm"))]
+            Decoder::Xz(decoder) => decoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Decoder::None(writer) => writer.flush(),
+            #[cfg(not(target_family = "wasm"))]
+            Decoder::Zstd(decoder) => decoder.flush(),
+            Decoder::Gzip(decoder) => decoder.flush(),
+            #[cfg(not(target_family = "wasm"))]
+            Decoder::Xz(decoder) => decoder.flush(),
+        }
+    }
+}
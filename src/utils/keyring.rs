@@ -0,0 +1,174 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+//! Cache of password-derived keys in the Linux session keyring, so files whose salt was seen
+//! recently skip the KDF. Keys live in a `stic` keyring linked to the session keyring, where the
+//! kernel drops them after their timeout or when the session ends, and never swaps them out.
+//! Elsewhere nothing is cached.
+
+use std::{io, time::Duration};
+
+use super::SecureBuffer;
+
+/// Password-derived key for `salt`, if it was cached and has not expired.
+pub fn cached_key(salt: &[u8]) -> Option<SecureBuffer> {
+    sys::read(&description(salt))
+}
+
+/// Caches the password-derived `key` for `salt` for `ttl`.
+pub fn cache_key(salt: &[u8], key: &[u8], ttl: Duration) -> io::Result<()> {
+    sys::add(&description(salt), key, ttl)
+}
+
+/// Drops every cached key.
+pub fn clear_keys() -> io::Result<()> {
+    sys::clear()
+}
+
+fn description(salt: &[u8]) -> String {
+    let hex: String = salt.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("stic:{hex}")
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::{
+        ffi::{c_long, CString},
+        io, ptr,
+        time::Duration,
+    };
+
+    use crate::{constants, utils::SecureBuffer};
+
+    // From <linux/keyctl.h>
+    const KEY_SPEC_SESSION_KEYRING: c_long = -3;
+    const KEYCTL_CLEAR: c_long = 7;
+    const KEYCTL_SEARCH: c_long = 10;
+    const KEYCTL_READ: c_long = 11;
+    const KEYCTL_SET_TIMEOUT: c_long = 15;
+
+    pub fn read(description: &str) -> Option<SecureBuffer> {
+        let keyring = search(KEY_SPEC_SESSION_KEYRING, "keyring", constants::KEYRING_NAME).ok()?;
+        let key = search(keyring, "user", description).ok()?;
+
+        let mut payload = SecureBuffer::new(constants::TOKEN_KEY_SIZE);
+        let length = keyctl(
+            KEYCTL_READ,
+            key,
+            payload.as_mut_ptr() as c_long,
+            payload.len() as c_long,
+        )
+        .ok()?;
+        (length as usize == payload.len()).then_some(payload)
+    }
+
+    pub fn add(description: &str, payload: &[u8], ttl: Duration) -> io::Result<()> {
+        let keyring = match search(KEY_SPEC_SESSION_KEYRING, "keyring", constants::KEYRING_NAME) {
+            Ok(keyring) => keyring,
+            Err(_) => add_key(
+                "keyring",
+                constants::KEYRING_NAME,
+                &[],
+                KEY_SPEC_SESSION_KEYRING,
+            )?,
+        };
+        let key = add_key("user", description, payload, keyring)?;
+        keyctl(KEYCTL_SET_TIMEOUT, key, ttl.as_secs().max(1) as c_long, 0)?;
+        Ok(())
+    }
+
+    pub fn clear() -> io::Result<()> {
+        match search(KEY_SPEC_SESSION_KEYRING, "keyring", constants::KEYRING_NAME) {
+            Ok(keyring) => keyctl(KEYCTL_CLEAR, keyring, 0, 0).map(|_| ()),
+            // Nothing was ever cached in this session
+            Err(_) => Ok(()),
+        }
+    }
+
+    fn search(keyring: c_long, key_type: &str, description: &str) -> io::Result<c_long> {
+        let key_type = CString::new(key_type)?;
+        let description = CString::new(description)?;
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_SEARCH,
+                keyring,
+                key_type.as_ptr(),
+                description.as_ptr(),
+                0 as c_long,
+            )
+        };
+        check(result)
+    }
+
+    fn add_key(
+        key_type: &str,
+        description: &str,
+        payload: &[u8],
+        keyring: c_long,
+    ) -> io::Result<c_long> {
+        let key_type = CString::new(key_type)?;
+        let description = CString::new(description)?;
+        let data = if payload.is_empty() {
+            ptr::null()
+        } else {
+            payload.as_ptr()
+        };
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_add_key,
+                key_type.as_ptr(),
+                description.as_ptr(),
+                data,
+                payload.len(),
+                keyring,
+            )
+        };
+        check(result)
+    }
+
+    fn keyctl(operation: c_long, key: c_long, arg3: c_long, arg4: c_long) -> io::Result<c_long> {
+        check(unsafe { libc::syscall(libc::SYS_keyctl, operation, key, arg3, arg4) })
+    }
+
+    fn check(result: c_long) -> io::Result<c_long> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::{io, time::Duration};
+
+    use crate::utils::SecureBuffer;
+
+    pub fn read(_description: &str) -> Option<SecureBuffer> {
+        None
+    }
+
+    pub fn add(_description: &str, _payload: &[u8], _ttl: Duration) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn clear() -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    alloc::{self, Layout},
+    fmt,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    slice,
+};
+
+use zeroize::Zeroize;
+
+/// Heap buffer for secrets (passwords, keys and plaintext) whose pages are locked in RAM so they
+/// never reach swap, left out of core dumps, and zeroed on drop.
+///
+/// Locking is best effort: past `RLIMIT_MEMLOCK` the buffer is still zeroed on drop. Each buffer
+/// takes whole pages, so it suits keys and segment-sized buffers rather than many small values.
+pub struct SecureBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+// The buffer owns its allocation like a `Box<[u8]>` does
+unsafe impl Send for SecureBuffer {}
+unsafe impl Sync for SecureBuffer {}
+
+impl SecureBuffer {
+    /// A zeroed buffer of `len` bytes.
+    pub fn new(len: usize) -> Self {
+        let page = page_size();
+        let size = len.max(1).div_ceil(page) * page;
+        let layout = Layout::from_size_align(size, page).unwrap();
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let Some(ptr) = NonNull::new(ptr) else {
+            alloc::handle_alloc_error(layout)
+        };
+        protect(ptr.as_ptr(), size);
+        SecureBuffer { ptr, len, layout }
+    }
+
+    /// Shortens the buffer to `len` bytes, keeping its allocation; longer lengths do nothing.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self[len..].zeroize();
+            self.len = len;
+        }
+    }
+}
+
+impl From<&[u8]> for SecureBuffer {
+    fn from(secret: &[u8]) -> Self {
+        let mut buffer = SecureBuffer::new(secret.len());
+        buffer.copy_from_slice(secret);
+        buffer
+    }
+}
+
+/// Moves a secret another API returned as a `Vec` in, zeroing the `Vec`.
+impl From<Vec<u8>> for SecureBuffer {
+    fn from(mut secret: Vec<u8>) -> Self {
+        let buffer = SecureBuffer::from(&secret[..]);
+        secret.zeroize();
+        buffer
+    }
+}
+
+impl Clone for SecureBuffer {
+    fn clone(&self) -> Self {
+        SecureBuffer::from(&self[..])
+    }
+}
+
+impl Deref for SecureBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for SecureBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl fmt::Debug for SecureBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecureBuffer({} bytes)", self.len)
+    }
+}
+
+impl Drop for SecureBuffer {
+    fn drop(&mut self) {
+        let size = self.layout.size();
+        unsafe {
+            slice::from_raw_parts_mut(self.ptr.as_ptr(), size).zeroize();
+            unprotect(self.ptr.as_ptr(), size);
+            alloc::dealloc(self.ptr.as_ptr(), self.layout);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn page_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+#[cfg(not(unix))]
+fn page_size() -> usize {
+    64
+}
+
+#[cfg(unix)]
+fn protect(ptr: *mut u8, size: usize) {
+    unsafe {
+        libc::mlock(ptr.cast(), size);
+        #[cfg(target_os = "linux")]
+        libc::madvise(ptr.cast(), size, libc::MADV_DONTDUMP);
+    }
+}
+
+/// Undoes `protect` before the pages go back to the allocator, which may hand them out again.
+#[cfg(unix)]
+fn unprotect(ptr: *mut u8, size: usize) {
+    unsafe {
+        #[cfg(target_os = "linux")]
+        libc::madvise(ptr.cast(), size, libc::MADV_DODUMP);
+        libc::munlock(ptr.cast(), size);
+    }
+}
+
+#[cfg(not(unix))]
+fn protect(_ptr: *mut u8, _size: usize) {}
+
+#[cfg(not(unix))]
+fn unprotect(_ptr: *mut u8, _size: usize) {}
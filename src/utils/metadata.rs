@@ -0,0 +1,255 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+#[cfg(unix)]
+use std::{
+    ffi::OsStr,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+};
+use std::{
+    fs::Metadata,
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::constants;
+
+use filetime::FileTime;
+use tar::{Entry, Header, HeaderMode};
+
+/// File metadata recorded in the archive on encryption and restored on decryption. Everything
+/// is preserved by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Preserve {
+    /// Permission bits, including setuid, setgid and sticky.
+    pub mode: bool,
+    /// Access and modification times, with nanosecond precision.
+    pub times: bool,
+    /// User and group ids, only restored when running as root.
+    pub ownership: bool,
+    /// Extended attributes, which also carry ACLs and SELinux labels.
+    pub xattrs: bool,
+}
+
+impl Preserve {
+    pub const ALL: Preserve = Preserve {
+        mode: true,
+        times: true,
+        ownership: true,
+        xattrs: true,
+    };
+
+    pub const NONE: Preserve = Preserve {
+        mode: false,
+        times: false,
+        ownership: false,
+        xattrs: false,
+    };
+}
+
+impl Default for Preserve {
+    fn default() -> Self {
+        Preserve::ALL
+    }
+}
+
+/// Tar header for `metadata`, with the fields that are not preserved reset.
+pub fn header(metadata: &Metadata, preserve: Preserve) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_metadata_in_mode(metadata, HeaderMode::Complete);
+
+    if !preserve.mode {
+        #[cfg(unix)]
+        let executable = metadata.is_dir() || metadata.mode() & 0o100 != 0;
+        #[cfg(not(unix))]
+        let executable = metadata.is_dir();
+        header.set_mode(if executable { 0o755 } else { 0o644 });
+    }
+    if !preserve.ownership {
+        header.set_uid(0);
+        header.set_gid(0);
+    }
+    header
+}
+
+/// PAX records carrying the metadata a plain tar header can't hold: times with sub-second
+/// precision, the access time and extended attributes. Empty if there is nothing to record.
+pub fn pax_records(path: &Path, metadata: &Metadata, preserve: Preserve) -> io::Result<Vec<u8>> {
+    let mut records = Vec::new();
+
+    if preserve.times {
+        let atime = FileTime::from_last_access_time(metadata);
+        let mtime = FileTime::from_last_modification_time(metadata);
+        push_record(&mut records, b"atime", format_time(atime).as_bytes());
+        push_record(&mut records, b"mtime", format_time(mtime).as_bytes());
+    }
+
+    #[cfg(unix)]
+    if preserve.xattrs {
+        // `metadata` only describes a symlink when it is archived as one
+        let follow = !metadata.is_symlink();
+        let names = if follow {
+            xattr::list_deref(path)
+        } else {
+            xattr::list(path)
+        };
+        let names = match names {
+            Ok(names) => names,
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => return Ok(records),
+            Err(e) => return Err(e),
+        };
+        for name in names {
+            let value = if follow {
+                xattr::get_deref(path, &name)?
+            } else {
+                xattr::get(path, &name)?
+            };
+            if let Some(value) = value {
+                let key = [constants::PAX_XATTR_PREFIX, name.as_encoded_bytes()].concat();
+                push_record(&mut records, &key, &value);
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// Access and modification times recorded for `entry`, falling back to the whole-second
+/// modification time of the header for archives written without PAX times.
+pub fn entry_times<R: Read>(entry: &mut Entry<R>) -> io::Result<(FileTime, FileTime)> {
+    let mut atime = None;
+    let mut mtime = None;
+
+    if let Some(extensions) = entry.pax_extensions()? {
+        for extension in extensions {
+            let extension = extension?;
+            let value = extension.value().ok().and_then(parse_time);
+            match extension.key_bytes() {
+                b"atime" => atime = value,
+                b"mtime" => mtime = value,
+                _ => {}
+            }
+        }
+    }
+
+    let mtime = match mtime {
+        Some(mtime) => mtime,
+        None => FileTime::from_unix_time(entry.header().mtime()? as i64, 0),
+    };
+    Ok((atime.unwrap_or(mtime), mtime))
+}
+
+/// Extended attributes recorded for `entry`, as names and values.
+pub fn entry_xattrs<R: Read>(entry: &mut Entry<R>) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut xattrs = Vec::new();
+    if let Some(extensions) = entry.pax_extensions()? {
+        for extension in extensions {
+            let extension = extension?;
+            if let Some(name) = extension
+                .key_bytes()
+                .strip_prefix(constants::PAX_XATTR_PREFIX)
+            {
+                xattrs.push((name.to_vec(), extension.value_bytes().to_vec()));
+            }
+        }
+    }
+    Ok(xattrs)
+}
+
+/// Sets `xattrs` on `path`. `security.*` and `trusted.*` attributes that this process is not
+/// allowed to set, or that the filesystem does not support, are skipped: an SELinux label from
+/// another host must not keep the rest of the archive from being restored.
+#[cfg(unix)]
+pub fn restore_xattrs(path: &Path, xattrs: &[(Vec<u8>, Vec<u8>)]) -> io::Result<()> {
+    // ENOTSUP and EOPNOTSUPP are the same on Linux but not everywhere
+    let refused = [libc::EPERM, libc::EACCES, libc::ENOTSUP, libc::EOPNOTSUPP];
+    for (name, value) in xattrs {
+        let Err(e) = xattr::set(path, OsStr::from_bytes(name), value) else {
+            continue;
+        };
+        let privileged = name.starts_with(b"security.") || name.starts_with(b"trusted.");
+        if privileged && e.raw_os_error().is_some_and(|code| refused.contains(&code)) {
+            continue;
+        }
+        return Err(io::Error::new(
+            e.kind(),
+            format!(
+                "Failed to set extended attribute {} on {}: {e}",
+                String::from_utf8_lossy(name),
+                path.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn restore_xattrs(_path: &Path, _xattrs: &[(Vec<u8>, Vec<u8>)]) -> io::Result<()> {
+    Ok(())
+}
+
+/// Ownership can only be handed to other users by root.
+#[cfg(unix)]
+pub fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+pub fn is_root() -> bool {
+    false
+}
+
+/// A record is `"<length> <key>=<value>\n"`, where the length counts itself.
+fn push_record(records: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    let base = key.len() + value.len() + 3;
+    let mut length = base + base.to_string().len();
+    if length.to_string().len() != base.to_string().len() {
+        length += 1;
+    }
+
+    records.extend_from_slice(format!("{length} ").as_bytes());
+    records.extend_from_slice(key);
+    records.push(b'=');
+    records.extend_from_slice(value);
+    records.push(b'\n');
+}
+
+/// PAX times are signed decimals, while `FileTime` keeps the nanoseconds positive: half a
+/// second before the epoch is -1 s + 500000000 ns, written `-0.500000000`.
+fn format_time(time: FileTime) -> String {
+    let (seconds, nanoseconds) = (time.unix_seconds(), time.nanoseconds());
+    if seconds < 0 && nanoseconds > 0 {
+        format!("-{}.{:09}", -(seconds + 1), 1_000_000_000 - nanoseconds)
+    } else {
+        format!("{seconds}.{nanoseconds:09}")
+    }
+}
+
+fn parse_time(value: &str) -> Option<FileTime> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let seconds: i64 = seconds.parse().ok()?;
+    let nanoseconds: u32 = format!("{fraction:0<9}").get(..9)?.parse().ok()?;
+    Some(match (negative, nanoseconds) {
+        (false, _) => FileTime::from_unix_time(seconds, nanoseconds),
+        (true, 0) => FileTime::from_unix_time(-seconds, 0),
+        (true, _) => FileTime::from_unix_time(-seconds - 1, 1_000_000_000 - nanoseconds),
+    })
+}
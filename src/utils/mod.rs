@@ -15,12 +15,36 @@ If not, see <https://www.gnu.org/licenses/>.
 Copyright (C) 2022 Salvador Bravo Garnica
 */
 
+mod armor;
+mod cancel;
+mod compression;
+mod keyring;
+mod memory;
+mod metadata;
+mod padding;
 mod paths;
+mod progress;
 mod secrets;
 
+pub use armor::{is_armored, ArmorReader, ArmorWriter};
+
+pub use cancel::CancelToken;
+
+pub use compression::{Compression, Decoder, Encoder};
+
+pub use keyring::{cache_key, cached_key, clear_keys};
+
+pub use memory::SecureBuffer;
+
+pub use metadata::Preserve;
+
+pub use padding::{Padding, Unpadder};
+
 pub use paths::{
-    from_tar, get_decrypted_path, get_encrypted_path, sanitize_path, to_tar, validate_decryption,
-    validate_encryption, TmpPath,
+    from_tar, get_anonymized_path, get_decrypted_path, get_encrypted_path, read_header,
+    sanitize_path, to_tar, validate_decryption, validate_encryption, Symlinks, TmpPath,
 };
 
+pub use progress::{format_bytes, Phase, Progress, ProgressBar, ProgressReader, ProgressWriter};
+
 pub use secrets::{key_from_password, read_password};
@@ -0,0 +1,177 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Cursor, Read, Write},
+    str::FromStr,
+};
+
+use crate::constants;
+
+/// Length-hiding padding, stored in the header by its `id`.
+///
+/// A padded payload is `content length (u64, big endian) || content || zeros`, all of it
+/// encrypted, so only the padded size is visible in the output file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Padding {
+    #[default]
+    None,
+    /// PADMÉ: leaks at most O(log log n) bits of the size, with at most 12% overhead.
+    Padme,
+    /// Rounds up to a multiple of `PADDING_BUCKET_SIZE`.
+    Bucket,
+}
+
+impl Padding {
+    pub const ALL: [Padding; 3] = [Padding::None, Padding::Padme, Padding::Bucket];
+
+    pub fn id(self) -> u8 {
+        match self {
+            Padding::None => constants::PADDING_NONE,
+            Padding::Padme => constants::PADDING_PADME,
+            Padding::Bucket => constants::PADDING_BUCKET,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, Box<dyn Error>> {
+        Padding::ALL
+            .into_iter()
+            .find(|padding| padding.id() == id)
+            .ok_or_else(|| Box::from(format!("Unknown padding id {id}")))
+    }
+
+    /// Size of the payload carrying `size` bytes of content, length prefix included.
+    pub fn padded_size(self, size: u64) -> u64 {
+        let prefixed = size + constants::PADDING_LENGTH_SIZE as u64;
+        match self {
+            Padding::None => size,
+            Padding::Padme => {
+                let exponent = prefixed.ilog2();
+                let exponent_bits = exponent.ilog2() + 1;
+                let mask = (1u64 << (exponent - exponent_bits)) - 1;
+                (prefixed + mask) & !mask
+            }
+            Padding::Bucket => prefixed.next_multiple_of(constants::PADDING_BUCKET_SIZE),
+        }
+    }
+
+    /// Wraps `content` of length `size` into the padded payload.
+    pub fn pad<'a, R: Read + Send + 'a>(self, content: R, size: u64) -> Box<dyn Read + Send + 'a> {
+        if self == Padding::None {
+            return Box::new(content);
+        }
+
+        let padding = self.padded_size(size) - size - constants::PADDING_LENGTH_SIZE as u64;
+        Box::new(
+            Cursor::new(size.to_be_bytes())
+                .chain(content)
+                .chain(io::repeat(0).take(padding)),
+        )
+    }
+
+    /// Wraps `writer` so that the padded payload written to it comes out as the original content.
+    pub fn unpad<W: Write>(self, writer: W) -> Unpadder<W> {
+        Unpadder {
+            inner: writer,
+            padded: self != Padding::None,
+            header: Vec::with_capacity(constants::PADDING_LENGTH_SIZE),
+            remaining: None,
+        }
+    }
+}
+
+impl fmt::Display for Padding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Padding::None => "none",
+            Padding::Padme => "padme",
+            Padding::Bucket => "bucket",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Padding {
+    type Err = Box<dyn Error>;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Padding::ALL
+            .into_iter()
+            .find(|padding| padding.to_string() == name)
+            .ok_or_else(|| Box::from(format!("Unknown padding {name}")))
+    }
+}
+
+pub struct Unpadder<W> {
+    inner: W,
+    padded: bool,
+    header: Vec<u8>,
+    remaining: Option<u64>,
+}
+
+impl<W> Unpadder<W> {
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Fails if the payload ended before the content length it recorded.
+    pub fn finish(self) -> io::Result<W> {
+        if self.padded && self.remaining != Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Padded content is shorter than its recorded length",
+            ));
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Unpadder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.padded {
+            return self.inner.write(buf);
+        }
+
+        let mut data = buf;
+
+        if self.remaining.is_none() {
+            let needed = constants::PADDING_LENGTH_SIZE - self.header.len();
+            let taken = needed.min(data.len());
+            self.header.extend_from_slice(&data[..taken]);
+            data = &data[taken..];
+
+            if self.header.len() < constants::PADDING_LENGTH_SIZE {
+                return Ok(buf.len());
+            }
+            let length = u64::from_be_bytes(self.header[..].try_into().unwrap());
+            self.remaining = Some(length);
+        }
+
+        let remaining = self.remaining.as_mut().unwrap();
+        let content = (*remaining).min(data.len() as u64) as usize;
+        self.inner.write_all(&data[..content])?;
+        *remaining -= content as u64;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -15,16 +15,36 @@ If not, see <https://www.gnu.org/licenses/>.
 Copyright (C) 2022 Salvador Bravo Garnica
 */
 
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
 use std::{
     error::Error,
-    fs::{remove_dir_all, remove_file, File},
-    io::Read,
-    path::{Path, PathBuf},
+    fs::{remove_dir_all, remove_file, File, Metadata},
+    io::{sink, BufReader, ErrorKind, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
 };
 
-use crate::constants;
+use crate::{
+    constants,
+    error::SticError,
+    format::{Header, Version},
+    utils::{
+        is_armored, metadata, ArmorReader, Compression, Preserve, ProgressReader, ProgressWriter,
+    },
+};
 
-use tar::{Archive, Builder};
+use tar::{Archive, Builder, EntryType};
+
+/// How symlinks met while archiving are handled. A symlink given as the path to encrypt is always
+/// followed, like `cp -H` does, so the archive holds the data rather than a link to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Stores the links themselves, as `tar` does.
+    #[default]
+    Preserve,
+    /// Stores what the links point to, failing on broken links and loops.
+    Follow,
+}
 
 pub fn sanitize_path(path: &str) -> Result<PathBuf, Box<dyn Error>> {
     let mut _path = PathBuf::from(path);
@@ -33,7 +53,14 @@ pub fn sanitize_path(path: &str) -> Result<PathBuf, Box<dyn Error>> {
     }
 
     if !_path.is_absolute() {
-        _path = _path.canonicalize()?;
+        // Only the parent is resolved, so a symlink given as path keeps its own name
+        _path = match (_path.parent(), _path.file_name()) {
+            (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+                parent.canonicalize()?.join(name)
+            }
+            (_, Some(name)) => std::env::current_dir()?.join(name),
+            _ => _path.canonicalize()?,
+        };
     }
 
     let str_path = _path.to_str().unwrap();
@@ -50,7 +77,7 @@ pub fn sanitize_path(path: &str) -> Result<PathBuf, Box<dyn Error>> {
         return Err(Box::from("Path does not have write permissions"));
     }
 
-    let parent = _path.parent().unwrap();
+    let parent = _path.parent().ok_or("Path has no parent directory")?;
 
     if parent.metadata()?.permissions().readonly() {
         return Err(Box::from("Parent dir does not have write permissions"));
@@ -73,8 +100,6 @@ pub fn validate_encryption(path: &Path) -> Result<(), Box<dyn Error>> {
 
     if size == 0 {
         return Err(Box::from("File is empty"));
-    } else if size >= constants::MAX_FILE_SIZE {
-        return Err(Box::from("File is too big"));
     }
 
     Ok(())
@@ -93,53 +118,323 @@ pub fn validate_decryption(path: &Path) -> Result<(), Box<dyn Error>> {
     } else {
         return Err(Box::from("Path is not encrypted"));
     }
-    let mut file = File::open(path)?;
-    let size = file.metadata().unwrap().len() as usize;
+    let (header, size) = read_header(path)?;
+    let size = size as usize;
 
-    if size >= constants::MAX_FILE_SIZE + constants::HEADER_SIZE + constants::STREAM_TAG_SIZE
-        || size <= constants::HEADER_SIZE + constants::STREAM_TAG_SIZE
+    if header.version() == Version::V1
+        && size >= constants::V1_MAX_FILE_SIZE + header.size() + constants::STREAM_TAG_SIZE
     {
         return Err(Box::from("An invalid file was provided"));
     }
 
-    let mut version = [0u8; constants::VERSION.len()];
-
-    file.read_exact(&mut version)?;
-
-    if version != constants::VERSION {
-        return Err(Box::from("Invalid file version"));
+    if size <= header.size() + constants::STREAM_TAG_SIZE {
+        return Err(Box::new(SticError::TruncatedPayload {
+            offset: size as u64,
+        }));
     }
 
     Ok(())
 }
 
+/// Parses the header of an encrypted file, armored or not, and returns it with the size of the
+/// binary container.
+pub fn read_header(path: &Path) -> Result<(Header, u64), Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let armored = is_armored(&mut file)?;
+
+    let size = if armored {
+        let size = std::io::copy(&mut ArmorReader::new(BufReader::new(&file)), &mut sink())?;
+        file.rewind()?;
+        size
+    } else {
+        file.metadata()?.len()
+    };
+    let reader: Box<dyn Read> = if armored {
+        Box::new(ArmorReader::new(BufReader::new(file)))
+    } else {
+        Box::new(file)
+    };
+
+    let mut bytes = Vec::with_capacity(constants::MAX_HEADER_SIZE);
+    reader
+        .take(constants::MAX_HEADER_SIZE as u64)
+        .read_to_end(&mut bytes)?;
+
+    Ok((Header::parse(&bytes)?, size))
+}
+
 pub fn get_encrypted_path(path: &Path) -> PathBuf {
     let str_path = path.to_str().unwrap();
     let new_path = PathBuf::from(format!("{}.{}", str_path, constants::EXTENSION));
     new_path
 }
 
+/// Like `get_encrypted_path`, but replaces the file name with a random one so the output does not
+/// reveal it. Decryption restores the original name from the archive.
+pub fn get_anonymized_path(path: &Path) -> PathBuf {
+    loop {
+        let filename: String = (0..constants::ANONYMIZED_NAME_SIZE)
+            .map(|_| format!("{:02x}", rand::random::<u8>()))
+            .collect();
+        let new_path =
+            path.parent()
+                .unwrap()
+                .join(format!("{}.{}", filename, constants::EXTENSION));
+        if !new_path.exists() {
+            return new_path;
+        }
+    }
+}
+
 pub fn get_decrypted_path(path: &Path) -> PathBuf {
     let str_path = path.to_str().unwrap();
     PathBuf::from(&str_path[..str_path.len() - (constants::EXTENSION.len() + 1)])
 }
 
-pub fn to_tar(input_path: &Path, output_path: &Path) -> Result<(), Box<dyn Error>> {
-    let mut tar_file = Builder::new(File::create(output_path)?);
+/// What to archive for `path`: its target if it is a symlink, whatever the policy.
+fn resolve_root(path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    if !path.symlink_metadata()?.is_symlink() {
+        return Ok(path.to_path_buf());
+    }
+    path.canonicalize().map_err(|e| {
+        if e.kind() == ErrorKind::NotFound {
+            Box::from(format!("{} is a broken symlink", path.display()))
+        } else {
+            Box::<dyn Error>::from(e)
+        }
+    })
+}
 
-    if input_path.is_dir() {
-        tar_file.append_dir_all(input_path.file_name().unwrap(), input_path)?;
+/// Metadata of `path` under the symlink policy, refusing anything that is not a file, a directory
+/// or a preserved symlink.
+fn stat(path: &Path, symlinks: Symlinks) -> Result<Metadata, Box<dyn Error>> {
+    let link = path.symlink_metadata()?;
+    let metadata = match symlinks {
+        Symlinks::Follow if link.is_symlink() => path.metadata().map_err(|e| {
+            if e.kind() == ErrorKind::NotFound {
+                Box::from(format!("{} is a broken symlink", path.display()))
+            } else {
+                Box::<dyn Error>::from(e)
+            }
+        })?,
+        _ => link,
+    };
+
+    match special_kind(&metadata) {
+        Some(kind) => Err(Box::from(format!(
+            "{} is {kind}, only files, directories and symlinks can be encrypted",
+            path.display()
+        ))),
+        None => Ok(metadata),
+    }
+}
+
+#[cfg(unix)]
+fn special_kind(metadata: &Metadata) -> Option<&'static str> {
+    let file_type = metadata.file_type();
+    if file_type.is_socket() {
+        Some("a socket")
+    } else if file_type.is_fifo() {
+        Some("a named pipe")
+    } else if file_type.is_block_device() {
+        Some("a block device")
+    } else if file_type.is_char_device() {
+        Some("a character device")
     } else {
-        let mut input_file = File::open(input_path)?;
-        tar_file.append_file(input_path.file_name().unwrap(), &mut input_file)?;
+        None
     }
-    tar_file.finish()?;
+}
+
+#[cfg(not(unix))]
+fn special_kind(_metadata: &Metadata) -> Option<&'static str> {
+    None
+}
+
+/// Records the directory being walked, failing if it is already one of its own ancestors, which
+/// only happens by following a symlink back up the tree.
+#[cfg(unix)]
+fn enter(
+    path: &Path,
+    metadata: &Metadata,
+    ancestors: &mut Vec<(u64, u64)>,
+) -> Result<(), Box<dyn Error>> {
+    let id = (metadata.dev(), metadata.ino());
+    if ancestors.contains(&id) {
+        return Err(Box::from(format!("{} is a symlink loop", path.display())));
+    }
+    ancestors.push(id);
+    Ok(())
+}
+
+/// Without inode numbers loops can't be recognized, following one fails once the path gets too
+/// long for the platform.
+#[cfg(not(unix))]
+fn enter(
+    _path: &Path,
+    _metadata: &Metadata,
+    _ancestors: &mut Vec<(u64, u64)>,
+) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-pub fn from_tar(input_tar: &Path, output_dir: &Path) -> Result<(), Box<dyn Error>> {
-    let mut tar_file = Archive::new(File::open(input_tar)?);
-    tar_file.unpack(output_dir)?;
+/// Archives `input_path` into `output_path`, reporting the bytes written so far against the size
+/// of the input, which is returned once done.
+pub fn to_tar(
+    input_path: &Path,
+    output_path: &Path,
+    compression: Compression,
+    level: u32,
+    preserve: Preserve,
+    symlinks: Symlinks,
+    progress: &dyn Fn(u64, u64) -> std::io::Result<()>,
+) -> Result<u64, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    walk(
+        &resolve_root(input_path)?,
+        Path::new(input_path.file_name().unwrap()),
+        symlinks,
+        &mut Vec::new(),
+        &mut entries,
+    )?;
+    let size = entries
+        .iter()
+        .filter(|(_, _, metadata)| !metadata.is_dir())
+        .map(|(_, _, metadata)| metadata.len())
+        .sum();
+
+    let output_file = compression.encoder(File::create(output_path)?, level)?;
+    let report = |done| progress(done, size);
+    let mut tar_file = Builder::new(ProgressWriter::new(output_file, &report));
+    for (path, name, metadata) in entries {
+        append_entry(&mut tar_file, &path, &name, &metadata, preserve)?;
+    }
+    tar_file.into_inner()?.into_inner().finish()?;
+    Ok(size)
+}
+
+/// Lists `path` and everything below it in archive order, so the tree is only walked once.
+fn walk(
+    path: &Path,
+    name: &Path,
+    symlinks: Symlinks,
+    ancestors: &mut Vec<(u64, u64)>,
+    entries: &mut Vec<(PathBuf, PathBuf, Metadata)>,
+) -> Result<(), Box<dyn Error>> {
+    let metadata = stat(path, symlinks)?;
+    if !metadata.is_dir() {
+        entries.push((path.to_path_buf(), name.to_path_buf(), metadata));
+        return Ok(());
+    }
+
+    enter(path, &metadata, ancestors)?;
+    entries.push((path.to_path_buf(), name.to_path_buf(), metadata));
+    let mut children = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<Result<Vec<_>, _>>()?;
+    children.sort();
+    for child in children {
+        walk(
+            &path.join(&child),
+            &name.join(&child),
+            symlinks,
+            ancestors,
+            entries,
+        )?;
+    }
+    ancestors.pop();
+    Ok(())
+}
+
+fn append_entry<W: Write>(
+    tar_file: &mut Builder<W>,
+    path: &Path,
+    name: &Path,
+    metadata: &Metadata,
+    preserve: Preserve,
+) -> Result<(), Box<dyn Error>> {
+    let records = metadata::pax_records(path, metadata, preserve)?;
+    if !records.is_empty() {
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(EntryType::XHeader);
+        header.set_size(records.len() as u64);
+        tar_file.append_data(&mut header, "PaxHeader", records.as_slice())?;
+    }
+
+    let mut header = metadata::header(metadata, preserve);
+    if metadata.is_dir() {
+        tar_file.append_data(&mut header, name, std::io::empty())?;
+    } else if metadata.is_symlink() {
+        tar_file.append_link(&mut header, name, std::fs::read_link(path)?)?;
+    } else {
+        tar_file.append_data(&mut header, name, File::open(path)?)?;
+    }
+    Ok(())
+}
+
+pub fn from_tar(
+    input_tar: &Path,
+    output_dir: &Path,
+    compression: Compression,
+    preserve: Preserve,
+    progress: &dyn Fn(u64) -> std::io::Result<()>,
+) -> Result<(), Box<dyn Error>> {
+    let input_file = ProgressReader::new(File::open(input_tar)?, progress);
+    let mut tar_file = Archive::new(compression.decoder(input_file)?);
+    tar_file.set_preserve_permissions(preserve.mode);
+    if !preserve.mode {
+        tar_file.set_mask(constants::UNPRESERVED_MODE_MASK);
+    }
+    tar_file.set_preserve_ownerships(preserve.ownership && metadata::is_root());
+    // Restored below, since tar gives up on the whole archive at the first one it can't set
+    tar_file.set_unpack_xattrs(false);
+    // Times are set once everything is extracted, since creating children changes the mtime of
+    // their directory.
+    tar_file.set_preserve_mtime(false);
+
+    std::fs::create_dir_all(output_dir)?;
+    let mut times = Vec::new();
+    // Directories are created as needed by their content and only get their permissions at the
+    // end, deepest first, so a read-only directory does not block its own extraction.
+    let mut directories = Vec::new();
+
+    for entry in tar_file.entries()? {
+        let mut entry = entry?;
+        let path: PathBuf = entry
+            .path()?
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+        let entry_times = metadata::entry_times(&mut entry)?;
+        // Links have none of their own: a symlink can't hold user attributes, and a hard link
+        // shares those of its target
+        let entry_type = entry.header().entry_type();
+        let xattrs = if preserve.xattrs && (entry_type.is_file() || entry_type.is_dir()) {
+            metadata::entry_xattrs(&mut entry)?
+        } else {
+            Vec::new()
+        };
+
+        if entry_type.is_dir() {
+            directories.push((entry, path, entry_times, xattrs));
+        } else if entry.unpack_in(output_dir)? {
+            metadata::restore_xattrs(&output_dir.join(&path), &xattrs)?;
+            times.push((path, entry_times));
+        }
+    }
+
+    directories.sort_by(|(_, a, _, _), (_, b, _, _)| b.cmp(a));
+    for (mut entry, path, entry_times, xattrs) in directories {
+        if entry.unpack_in(output_dir)? {
+            metadata::restore_xattrs(&output_dir.join(&path), &xattrs)?;
+            times.push((path, entry_times));
+        }
+    }
+
+    if preserve.times {
+        for (path, (atime, mtime)) in times {
+            filetime::set_symlink_file_times(output_dir.join(path), atime, mtime)?;
+        }
+    }
     Ok(())
 }
 
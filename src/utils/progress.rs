@@ -0,0 +1,229 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    fmt,
+    io::{Read, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::constants;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Tar,
+    Kdf,
+    Encrypt,
+    Decrypt,
+    Extract,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Phase::Tar => "tar",
+            Phase::Kdf => "kdf",
+            Phase::Encrypt => "encrypt",
+            Phase::Decrypt => "decrypt",
+            Phase::Extract => "extract",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Receives `done`/`total` byte counts as each phase of an operation advances.
+///
+/// `update` is called once per buffer, so implementations should be cheap.
+pub trait Progress: Send + Sync {
+    fn update(&self, phase: Phase, done: u64, total: u64);
+}
+
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a dyn Fn(u64) -> std::io::Result<()>,
+    done: u64,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub fn new(inner: R, progress: &'a dyn Fn(u64) -> std::io::Result<()>) -> Self {
+        Self {
+            inner,
+            progress,
+            done: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.done += count as u64;
+        (self.progress)(self.done)?;
+        Ok(count)
+    }
+}
+
+pub struct ProgressWriter<'a, W> {
+    inner: W,
+    progress: &'a dyn Fn(u64) -> std::io::Result<()>,
+    done: u64,
+}
+
+impl<'a, W: Write> ProgressWriter<'a, W> {
+    pub fn new(inner: W, progress: &'a dyn Fn(u64) -> std::io::Result<()>) -> Self {
+        Self {
+            inner,
+            progress,
+            done: 0,
+        }
+    }
+}
+
+impl<W> ProgressWriter<'_, W> {
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.done += count as u64;
+        (self.progress)(self.done)?;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Terminal progress bar drawn on stderr, showing throughput and ETA for the current phase.
+pub struct ProgressBar {
+    state: Mutex<BarState>,
+}
+
+struct BarState {
+    phase: Option<Phase>,
+    started: Instant,
+    drawn: Instant,
+}
+
+impl ProgressBar {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            state: Mutex::new(BarState {
+                phase: None,
+                started: now,
+                drawn: now,
+            }),
+        }
+    }
+
+    /// Ends the line of the last drawn phase.
+    pub fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.phase.take().is_some() {
+            eprintln!();
+        }
+    }
+}
+
+impl Default for ProgressBar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Progress for ProgressBar {
+    fn update(&self, phase: Phase, done: u64, total: u64) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        if state.phase != Some(phase) {
+            if state.phase.is_some() {
+                eprintln!();
+            }
+            state.phase = Some(phase);
+            state.started = now;
+        } else if done < total
+            && now.duration_since(state.drawn)
+                < Duration::from_millis(constants::PROGRESS_REFRESH_MS)
+        {
+            return;
+        }
+        state.drawn = now;
+
+        let done = done.min(total);
+        let ratio = if total == 0 {
+            1.0
+        } else {
+            done as f64 / total as f64
+        };
+        let filled = (ratio * constants::PROGRESS_BAR_WIDTH as f64) as usize;
+        let bar = format!(
+            "{}{}",
+            "=".repeat(filled),
+            " ".repeat(constants::PROGRESS_BAR_WIDTH - filled)
+        );
+
+        let elapsed = now.duration_since(state.started).as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            done as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta = if rate > 0.0 {
+            format_duration((total - done) as f64 / rate)
+        } else {
+            String::from("--:--")
+        };
+
+        eprint!(
+            "\r{phase:>7} [{bar}] {:>3}% {}/s ETA {eta}",
+            (ratio * 100.0) as u8,
+            format_bytes(rate),
+        );
+    }
+}
+
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:>6.1} {}", UNITS[unit])
+}
+
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            (seconds / 60) % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
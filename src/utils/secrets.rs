@@ -15,43 +15,32 @@ If not, see <https://www.gnu.org/licenses/>.
 Copyright (C) 2022 Salvador Bravo Garnica
 */
 
-use std::error::Error;
+use std::{error::Error, io};
 
-use crate::constants;
-
-use openssl::{hash::MessageDigest, pkcs5::pbkdf2_hmac};
-
-use zeroize::Zeroizing;
+use crate::{constants, core::PasswordKdf, utils::SecureBuffer};
 
 pub fn key_from_password(
+    kdf: &dyn PasswordKdf,
     password: &[u8],
     salt: &[u8],
-) -> Result<Zeroizing<Vec<u8>>, Box<dyn Error>> {
-    let hash: MessageDigest = MessageDigest::sha3_512();
+) -> Result<SecureBuffer, Box<dyn Error>> {
+    let mut key = SecureBuffer::new(constants::TOKEN_KEY_SIZE);
 
-    let mut key = Zeroizing::new(vec![0u8; constants::TOKEN_KEY_SIZE]);
-
-    pbkdf2_hmac(
-        password,
-        salt,
-        constants::PBKDF2_HMAC_ITERATIONS,
-        hash,
-        &mut key,
-    )?;
+    kdf.derive(password, salt, &mut key)?;
 
     Ok(key)
 }
 
-pub fn read_password(confirmation: bool) -> Result<Zeroizing<String>, Box<dyn Error>> {
-    let password = Zeroizing::new(rpassword::prompt_password("password: ")?);
+/// Prompts for a password, returned as UTF-8 bytes.
+pub fn read_password(confirmation: bool) -> Result<SecureBuffer, Box<dyn Error>> {
+    let password = SecureBuffer::from(prompt("password: ")?.into_bytes());
 
-    verify_password(&password)?;
+    verify_password(std::str::from_utf8(&password)?)?;
 
     if confirmation {
-        let password_confirmation =
-            Zeroizing::new(rpassword::prompt_password("repeat password: ")?);
+        let password_confirmation = SecureBuffer::from(prompt("repeat password: ")?.into_bytes());
 
-        if password != password_confirmation {
+        if password[..] != password_confirmation[..] {
             return Err(Box::from("Passwords do not match"));
         }
     }
@@ -59,6 +48,19 @@ pub fn read_password(confirmation: bool) -> Result<Zeroizing<String>, Box<dyn Er
     Ok(password)
 }
 
+#[cfg(not(target_family = "wasm"))]
+fn prompt(label: &str) -> io::Result<String> {
+    rpassword::prompt_password(label)
+}
+
+#[cfg(target_family = "wasm")]
+fn prompt(_label: &str) -> io::Result<String> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "There is no terminal to prompt for a password on",
+    ))
+}
+
 fn verify_password(password: &str) -> Result<(), Box<dyn Error>> {
     if password.len() < constants::PASSWORD_MIN {
         return Err(Box::from(format!(
@@ -0,0 +1,161 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+//! Browser and Node bindings, enabled by the `wasm` feature on top of the RustCrypto backend.
+//!
+//! Everything works on bytes: `encrypt` produces the same `.ic` file the CLI writes for a single
+//! file, and `list` and `extract` read the archive inside any file encrypted from a file or a
+//! directory. Version 1 files hold a single stream and are not supported. Failures are thrown as
+//! `Error`s named after the `SticError` variant, or `Error` for anything else.
+
+use std::{
+    error::Error,
+    io::Read,
+    path::{Component, Path},
+};
+
+use js_sys::JsString;
+use tar::{Archive, Builder, EntryType, Header};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    core::{decrypt_bytes, encrypt_bytes, Options},
+    error::SticError,
+};
+
+/// One file, directory or link in an encrypted archive.
+#[wasm_bindgen(getter_with_clone)]
+pub struct Entry {
+    pub path: String,
+    /// In bytes, 0 for anything but files.
+    pub size: f64,
+    /// `file`, `directory`, `symlink`, `hardlink` or `other`.
+    pub kind: String,
+}
+
+/// Encrypts `data` as a file called `name`, last modified at `modified` (milliseconds since the
+/// epoch, as in `File.lastModified`).
+#[wasm_bindgen]
+pub fn encrypt(name: &str, data: &[u8], password: &str, modified: f64) -> Result<Vec<u8>, JsValue> {
+    let archive = single_file_archive(name, data, modified).map_err(to_js)?;
+    encrypt_bytes(&archive, password, &Options::new()).map_err(to_js)
+}
+
+/// Decrypts a whole file into the tar archive it holds.
+#[wasm_bindgen]
+pub fn decrypt(data: &[u8], password: &str) -> Result<Vec<u8>, JsValue> {
+    let archive = decrypt_bytes(data, password, &Options::new()).map_err(to_js)?;
+    Ok(archive.to_vec())
+}
+
+/// Lists the archive inside an encrypted file.
+#[wasm_bindgen]
+pub fn list(data: &[u8], password: &str) -> Result<Vec<Entry>, JsValue> {
+    entries(data, password).map_err(to_js)
+}
+
+/// Returns the content of the file at `path` in the archive inside an encrypted file.
+#[wasm_bindgen]
+pub fn extract(data: &[u8], password: &str, path: &str) -> Result<Vec<u8>, JsValue> {
+    read_file(data, password, path).map_err(to_js)
+}
+
+fn single_file_archive(name: &str, data: &[u8], modified: f64) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut components = Path::new(name).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return Err(Box::from(format!("{name} is not a file name")));
+    }
+
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime((modified / 1000.0) as u64);
+
+    let mut builder = Builder::new(Vec::new());
+    builder.append_data(&mut header, name, data)?;
+    Ok(builder.into_inner()?)
+}
+
+fn entries(data: &[u8], password: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let plaintext = decrypt_bytes(data, password, &Options::new())?;
+    let mut archive = Archive::new(&plaintext[..]);
+
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let kind = match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous => "file",
+            EntryType::Directory => "directory",
+            EntryType::Symlink => "symlink",
+            EntryType::Link => "hardlink",
+            _ => "other",
+        };
+        entries.push(Entry {
+            path: entry.path()?.to_string_lossy().into_owned(),
+            size: if kind == "file" {
+                entry.size() as f64
+            } else {
+                0.0
+            },
+            kind: String::from(kind),
+        });
+    }
+    Ok(entries)
+}
+
+fn read_file(data: &[u8], password: &str, path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let plaintext = decrypt_bytes(data, password, &Options::new())?;
+    let mut archive = Archive::new(&plaintext[..]);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_file() && *entry.path()? == *Path::new(path) {
+            let mut content = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut content)?;
+            return Ok(content);
+        }
+    }
+    Err(Box::from(format!("There is no file {path} in the archive")))
+}
+
+fn to_js(e: Box<dyn Error>) -> JsValue {
+    let name = match e.downcast_ref() {
+        Some(SticError::Cancelled) => "Cancelled",
+        Some(SticError::CorruptHeader { .. }) => "CorruptHeader",
+        Some(SticError::WrongPassword) => "WrongPassword",
+        Some(SticError::CorruptPayload { .. }) => "CorruptPayload",
+        Some(SticError::TruncatedPayload { .. }) => "TruncatedPayload",
+        None => "Error",
+    };
+    let error = js_sys::Error::new(&e.to_string());
+    error.set_name(name);
+    if let Some(
+        SticError::CorruptHeader { offset, .. }
+        | SticError::CorruptPayload { offset }
+        | SticError::TruncatedPayload { offset },
+    ) = e.downcast_ref()
+    {
+        let _ = js_sys::Reflect::set(&error, &JsString::from("offset"), &(*offset as f64).into());
+    }
+    error.into()
+}
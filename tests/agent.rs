@@ -0,0 +1,133 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+#![cfg(unix)]
+
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+mod common;
+
+use common::{decrypt_error, TestDir, PASSWORD};
+use stic::{
+    agent_add, agent_identities, agent_lock, decrypt_file_into, run_agent, CancelToken, Options,
+    SticError,
+};
+
+const WRONG_PASSWORD: &str = "Wr0ng password!";
+
+/// Starts an agent on `socket` and waits until it answers.
+fn start_agent(socket: &Path, options: Options) -> thread::JoinHandle<Result<(), String>> {
+    let agent_socket = socket.to_path_buf();
+    let agent =
+        thread::spawn(move || run_agent(&agent_socket, &options).map_err(|e| e.to_string()));
+    while agent_identities(socket).is_none() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    agent
+}
+
+fn mode(path: &Path) -> u32 {
+    fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+#[test]
+fn unwraps_tokens_until_locked() {
+    let dir = TestDir::new("agent");
+    let encrypted = dir.encrypted(b"agent", &Options::new());
+    let socket = dir.0.join("agent.sock");
+    start_agent(&socket, Options::new());
+    agent_add(&socket, PASSWORD.as_bytes(), None).unwrap();
+    assert_eq!(agent_identities(&socket), Some(1));
+
+    // With the agent holding the password, the one given is not used
+    let agent = Options::new().agent(socket.clone());
+    let restored = decrypt_file_into(&encrypted, WRONG_PASSWORD, &dir.0.join("out"), &agent);
+    assert_eq!(fs::read(restored.unwrap()).unwrap(), b"agent");
+
+    agent_lock(&socket).unwrap();
+    assert_eq!(agent_identities(&socket), Some(0));
+    let error = decrypt_error(&encrypted, WRONG_PASSWORD, &agent);
+    assert_eq!(error, SticError::WrongPassword);
+}
+
+#[test]
+fn forgets_identities_after_timeout() {
+    let dir = TestDir::new("agent-timeout");
+    let encrypted = dir.encrypted(b"agent", &Options::new());
+    let socket = dir.0.join("agent.sock");
+    start_agent(&socket, Options::new());
+    agent_add(&socket, PASSWORD.as_bytes(), Some(Duration::from_secs(1))).unwrap();
+    assert_eq!(agent_identities(&socket), Some(1));
+
+    thread::sleep(Duration::from_millis(1100));
+    assert_eq!(agent_identities(&socket), Some(0));
+    let agent = Options::new().agent(socket.clone());
+    let error = decrypt_error(&encrypted, WRONG_PASSWORD, &agent);
+    assert_eq!(error, SticError::WrongPassword);
+}
+
+#[test]
+fn locks_while_a_client_waits() {
+    let dir = TestDir::new("agent-lock");
+    let encrypted = dir.encrypted(b"agent", &Options::new());
+    let socket = dir.0.join("agent.sock");
+    start_agent(&socket, Options::new());
+    agent_add(&socket, PASSWORD.as_bytes(), None).unwrap();
+
+    let agent = Options::new().agent(socket.clone());
+    let waiting = thread::spawn(move || decrypt_error(&encrypted, WRONG_PASSWORD, &agent));
+    thread::sleep(Duration::from_millis(100));
+
+    // The lock is not held up by the KDF the other client waits on, whose key is then dropped
+    agent_lock(&socket).unwrap();
+    assert!(!waiting.is_finished());
+    assert_eq!(agent_identities(&socket), Some(0));
+    assert_eq!(waiting.join().unwrap(), SticError::WrongPassword);
+}
+
+#[test]
+fn keeps_the_socket_private() {
+    let dir = TestDir::new("agent-socket");
+    let socket: PathBuf = dir.0.join("private").join("agent.sock");
+    start_agent(&socket, Options::new());
+    assert_eq!(mode(socket.parent().unwrap()), 0o700);
+    assert_eq!(mode(&socket), 0o600);
+
+    // A socket others could connect to is not trusted with passwords
+    fs::set_permissions(&socket, fs::Permissions::from_mode(0o666)).unwrap();
+    assert_eq!(agent_identities(&socket), None);
+    assert!(agent_add(&socket, PASSWORD.as_bytes(), None).is_err());
+}
+
+#[test]
+fn stops_when_cancelled() {
+    let dir = TestDir::new("agent-cancel");
+    let socket = dir.0.join("agent.sock");
+    let cancel = CancelToken::new();
+    let agent = start_agent(&socket, Options::new().cancel(cancel.clone()));
+
+    cancel.cancel();
+    let error = agent.join().unwrap().unwrap_err();
+    assert_eq!(error, SticError::Cancelled.to_string());
+    assert!(!socket.exists());
+}
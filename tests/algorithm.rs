@@ -0,0 +1,43 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::fs;
+
+mod common;
+
+use common::{data, TestDir, PASSWORD};
+use stic::{decrypt_file_into, read_header, Algorithm, Options};
+
+#[test]
+fn round_trips_each_algorithm() {
+    // A few segments, the last one short
+    let data = data((3 << 20) + 1_234);
+
+    for algorithm in Algorithm::ALL {
+        let dir = TestDir::new(&format!("algorithm-{}", algorithm.id()));
+        let encrypted = dir.encrypted(&data, &Options::new().algorithm(algorithm));
+
+        let header = read_header(&encrypted).unwrap().0;
+        assert_eq!(header.algorithm, algorithm);
+        assert_eq!(header.nonce.len(), algorithm.nonce_prefix_size());
+
+        // Decryption takes the algorithm from the header, whatever the options say
+        let restored =
+            decrypt_file_into(&encrypted, PASSWORD, &dir.0.join("out"), &Options::new()).unwrap();
+        assert_eq!(fs::read(restored).unwrap(), data);
+    }
+}
@@ -0,0 +1,54 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::fs;
+
+mod common;
+
+use common::{TestDir, PASSWORD};
+use stic::{decrypt_file_into, encrypt_file_with, get_anonymized_path, Options};
+
+#[test]
+fn restores_anonymized_names() {
+    let dir = TestDir::new("anonymize");
+    let (input, _) = dir.plaintext(b"named content");
+
+    let encrypted = get_anonymized_path(&input);
+    assert_eq!(encrypted.parent(), input.parent());
+    let name = encrypted.file_name().unwrap().to_str().unwrap();
+    assert!(name.ends_with(".ic") && !name.contains("file"), "{name}");
+    assert_ne!(get_anonymized_path(&input), encrypted);
+
+    encrypt_file_with(&input, PASSWORD, &encrypted, &Options::new()).unwrap();
+    let output = dir.0.join("out");
+
+    // The original is still there, so decrypting next to it would overwrite it
+    let error = decrypt_file_into(&encrypted, PASSWORD, &dir.0, &Options::new()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        format!("{} already exists", input.display())
+    );
+    assert_eq!(fs::read(&input).unwrap(), b"named content");
+
+    let restored = decrypt_file_into(&encrypted, PASSWORD, &output, &Options::new()).unwrap();
+    assert_eq!(restored, output.join("file.txt"));
+    assert_eq!(fs::read(restored).unwrap(), b"named content");
+
+    // Nor are earlier restores overwritten
+    assert!(decrypt_file_into(&encrypted, PASSWORD, &output, &Options::new()).is_err());
+    assert_eq!(fs::read_dir(&output).unwrap().count(), 1);
+}
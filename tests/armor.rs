@@ -0,0 +1,62 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::fs;
+
+mod common;
+
+use common::{TestDir, PASSWORD};
+use stic::{decrypt_file_into, encrypt_file_with, validate_decryption, Options};
+
+#[test]
+fn round_trips_armored_files() {
+    let dir = TestDir::new("armor");
+    let input = dir.0.join("notes.txt");
+    let encrypted = dir.0.join("notes.txt.ic");
+    fs::write(&input, "pasted into a ticket\n".repeat(100)).unwrap();
+
+    encrypt_file_with(&input, PASSWORD, &encrypted, &Options::new().armor(true)).unwrap();
+
+    let armored = fs::read_to_string(&encrypted).unwrap();
+    let lines: Vec<&str> = armored.lines().collect();
+    assert_eq!(lines[0], "-----BEGIN STIC ENCRYPTED FILE-----");
+    assert_eq!(lines[lines.len() - 1], "-----END STIC ENCRYPTED FILE-----");
+    assert!(lines.iter().all(|line| line.len() <= 64));
+
+    validate_decryption(&encrypted).unwrap();
+    let restored = decrypt_file_into(&encrypted, PASSWORD, &dir.0.join("out"), &Options::new());
+    assert_eq!(
+        fs::read(restored.unwrap()).unwrap(),
+        fs::read(&input).unwrap()
+    );
+}
+
+#[test]
+fn rejects_truncated_armor() {
+    let dir = TestDir::new("armor-truncated");
+    let input = dir.0.join("notes.txt");
+    let encrypted = dir.0.join("notes.txt.ic");
+    fs::write(&input, "pasted into an email\n").unwrap();
+
+    encrypt_file_with(&input, PASSWORD, &encrypted, &Options::new().armor(true)).unwrap();
+    let armored = fs::read_to_string(&encrypted).unwrap();
+    let cut = armored.trim_end().rfind('\n').unwrap();
+    fs::write(&encrypted, &armored[..cut]).unwrap();
+
+    let error = validate_decryption(&encrypted).unwrap_err();
+    assert_eq!(error.to_string(), "Armored file is missing its END marker");
+}
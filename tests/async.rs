@@ -0,0 +1,86 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+#![cfg(feature = "async")]
+
+use std::{fs, future::Future};
+
+mod common;
+
+use common::{data, TestDir, PASSWORD};
+use stic::{
+    decrypt_file_into_async, encrypt_file_async, AsyncDecryptReader, AsyncEncryptWriter,
+    Compression, Options, SticError,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+#[test]
+fn round_trips_adapters() {
+    let data = data(5 << 19);
+    let options = Options::new().compression(Compression::Gzip);
+
+    block_on(async {
+        let mut writer = AsyncEncryptWriter::new(Vec::new(), PASSWORD, &options)
+            .await
+            .unwrap();
+        for chunk in data.chunks(70_000) {
+            writer.write_all(chunk).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+        let encrypted = writer.into_inner();
+
+        let mut reader = AsyncDecryptReader::new(&encrypted[..], PASSWORD, &Options::new())
+            .await
+            .unwrap();
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).await.unwrap();
+        assert_eq!(decrypted, data);
+
+        let error = AsyncDecryptReader::new(&encrypted[..], "Wr0ng password!", &Options::new())
+            .await
+            .err()
+            .unwrap();
+        let error = error.get_ref().and_then(|e| e.downcast_ref::<SticError>());
+        assert_eq!(error, Some(&SticError::WrongPassword));
+    });
+}
+
+#[test]
+fn round_trips_files() {
+    let dir = TestDir::new("async-files");
+    let (input, encrypted) = dir.plaintext(b"async round-trip");
+
+    block_on(async {
+        encrypt_file_async(&input, PASSWORD, &encrypted, &Options::new())
+            .await
+            .unwrap();
+        fs::remove_file(&input).unwrap();
+
+        let restored =
+            decrypt_file_into_async(&encrypted, PASSWORD, &dir.0.join("out"), &Options::new())
+                .await
+                .unwrap();
+        assert_eq!(fs::read(restored).unwrap(), b"async round-trip");
+    });
+}
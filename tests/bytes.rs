@@ -0,0 +1,98 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+mod common;
+
+use std::fs::{self, File};
+
+use common::{data, TestDir, PASSWORD};
+use stic::{
+    decrypt_bytes, decrypt_stream, encrypt_bytes, encrypt_stream, Compression, Decryptor,
+    Encryptor, Options, Padding, SticError,
+};
+
+/// A little over two segments, so the last one is short.
+const SIZE: usize = 5 << 19;
+
+#[test]
+fn round_trips_bytes() {
+    let options = Options::new().compression(Compression::Zstd);
+    let encrypted = encrypt_bytes(&data(SIZE), PASSWORD, &options).unwrap();
+    let decrypted = decrypt_bytes(&encrypted, PASSWORD, &Options::new()).unwrap();
+    assert_eq!(*decrypted, data(SIZE));
+}
+
+#[test]
+fn round_trips_streams_in_pieces() {
+    let mut encrypted = Vec::new();
+    encrypt_stream(
+        &mut &data(SIZE)[..],
+        PASSWORD,
+        &mut encrypted,
+        &Options::new(),
+    )
+    .unwrap();
+
+    // Chunks that straddle the header and segment boundaries
+    let mut decryptor = Decryptor::new(PASSWORD, &Options::new());
+    let mut decrypted = Vec::new();
+    for chunk in encrypted.chunks(100_003) {
+        decrypted.extend_from_slice(&decryptor.update(chunk).unwrap());
+    }
+    decrypted.extend_from_slice(&decryptor.finish().unwrap());
+    assert_eq!(decrypted, data(SIZE));
+
+    let dir = TestDir::new("bytes-stream");
+    let path = dir.0.join("data.ic");
+    fs::write(&path, &encrypted).unwrap();
+    let mut streamed = Vec::new();
+    decrypt_stream(
+        &mut File::open(&path).unwrap(),
+        PASSWORD,
+        &mut streamed,
+        &Options::new(),
+    )
+    .unwrap();
+    assert_eq!(streamed, data(SIZE));
+}
+
+#[test]
+fn round_trips_empty_input() {
+    let encryptor = Encryptor::new(PASSWORD, &Options::new()).unwrap();
+    let encrypted = encryptor.finish().unwrap();
+    assert!(decrypt_bytes(&encrypted, PASSWORD, &Options::new())
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn reports_truncation() {
+    let encrypted = encrypt_bytes(&data(SIZE), PASSWORD, &Options::new()).unwrap();
+    let end = encrypted.len() - (SIZE % (1 << 20)) - 16;
+
+    let error = decrypt_bytes(&encrypted[..end], PASSWORD, &Options::new()).unwrap_err();
+    assert_eq!(
+        error.downcast_ref(),
+        Some(&SticError::TruncatedPayload { offset: end as u64 })
+    );
+}
+
+#[test]
+fn refuses_padding() {
+    let options = Options::new().padding(Padding::Padme);
+    assert!(Encryptor::new(PASSWORD, &options).is_err());
+}
@@ -0,0 +1,121 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{fs, path::Path, sync::Arc, thread, time::Duration};
+
+mod common;
+
+use common::{data, decrypt_error, TestDir, PASSWORD};
+use stic::{encrypt_file_with, CancelToken, Options, Phase, Progress, SticError};
+
+/// Three segments, so that every phase reports progress more than once.
+const SIZE: usize = 3 << 20;
+
+/// Cancels the operation from inside its own progress callback, once `phase` has started.
+struct CancelDuring {
+    phase: Phase,
+    cancel: CancelToken,
+}
+
+impl Progress for CancelDuring {
+    fn update(&self, phase: Phase, done: u64, _total: u64) {
+        if phase == self.phase && done > 0 {
+            self.cancel.cancel();
+        }
+    }
+}
+
+fn cancelled_during(phase: Phase) -> Options {
+    let cancel = CancelToken::new();
+    Options::new()
+        .cancel(cancel.clone())
+        .progress(Arc::new(CancelDuring { phase, cancel }))
+}
+
+fn entries(dir: &Path) -> Vec<String> {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn cancels_encryption() {
+    for phase in [Phase::Tar, Phase::Kdf, Phase::Encrypt] {
+        let dir = TestDir::new(&format!("cancel-{phase}"));
+        let (input, encrypted) = dir.plaintext(&data(SIZE));
+
+        let error = encrypt_file_with(&input, PASSWORD, &encrypted, &cancelled_during(phase));
+        let error = error.unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&SticError::Cancelled), "{phase}");
+        // Neither the partial output nor the temporary tar is left behind
+        assert_eq!(entries(&dir.0), ["file.txt", "out"], "{phase}");
+    }
+}
+
+#[test]
+fn cancels_decryption() {
+    let dir = TestDir::new("cancel-decrypt");
+    let encrypted = dir.encrypted(&data(SIZE), &Options::new());
+
+    for phase in [Phase::Kdf, Phase::Decrypt, Phase::Extract] {
+        let error = decrypt_error(&encrypted, PASSWORD, &cancelled_during(phase));
+        assert_eq!(error, SticError::Cancelled, "{phase}");
+        assert!(entries(&dir.0.join("out")).is_empty(), "{phase}");
+        assert_eq!(entries(&dir.0), ["file.txt.ic", "out"], "{phase}");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_exits_on_interrupt() {
+    use std::process::{Command, Stdio};
+    use stic::{agent_add, agent_identities, run_agent};
+
+    let dir = TestDir::new("cancel-cli");
+    let encrypted = dir.encrypted(&data(32 << 20), &Options::new());
+
+    // The agent holds the password, so the CLI runs without a terminal to prompt on
+    let socket = dir.0.join("agent.sock");
+    let agent_socket = socket.clone();
+    thread::spawn(move || run_agent(&agent_socket, &Options::new()).is_ok());
+    while agent_identities(&socket).is_none() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    agent_add(&socket, PASSWORD.as_bytes(), None).unwrap();
+
+    let before = entries(&dir.0);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_stic-cli"))
+        .args(["--agent", "-d"])
+        .arg(&encrypted)
+        .env("STIC_AGENT_SOCK", &socket)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // Interrupted once it has started writing its temporary files
+    while entries(&dir.0) == before {
+        assert!(child.try_wait().unwrap().is_none(), "finished too early");
+        thread::sleep(Duration::from_millis(1));
+    }
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT) };
+    assert_eq!(child.wait().unwrap().code(), Some(130));
+    assert_eq!(entries(&dir.0), before);
+}
@@ -0,0 +1,77 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+// Each test binary uses its own subset of these
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use stic::{decrypt_file_into, encrypt_file_with, Options, SticError};
+
+pub const PASSWORD: &str = "Passw0rd!";
+
+/// `size` bytes of a pattern that does not line up with blocks or segments, so that data out of
+/// place does not compare equal.
+pub fn data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i % 251) as u8).collect()
+}
+
+/// Scratch directory, with an `out` directory to decrypt into, removed on drop.
+pub struct TestDir(pub PathBuf);
+
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("stic-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join("out")).unwrap();
+        TestDir(path)
+    }
+
+    /// Writes `data` to `file.txt` and returns its path with the one to encrypt it to.
+    pub fn plaintext(&self, data: &[u8]) -> (PathBuf, PathBuf) {
+        let input = self.0.join("file.txt");
+        fs::write(&input, data).unwrap();
+        (input, self.0.join("file.txt.ic"))
+    }
+
+    /// Encrypts `data` under `PASSWORD`, leaving only the encrypted file, whose path it returns.
+    pub fn encrypted(&self, data: &[u8], options: &Options) -> PathBuf {
+        let (input, encrypted) = self.plaintext(data);
+        encrypt_file_with(&input, PASSWORD, &encrypted, options).unwrap();
+        fs::remove_file(input).unwrap();
+        encrypted
+    }
+}
+
+/// Decrypts `path` into the `out` directory next to it, expecting a `SticError`.
+pub fn decrypt_error(path: &Path, password: &str, options: &Options) -> SticError {
+    let output = path.parent().unwrap().join("out");
+    let error = decrypt_file_into(path, password, &output, options).unwrap_err();
+    match error.downcast::<SticError>() {
+        Ok(error) => *error,
+        Err(error) => panic!("untyped error: {error}"),
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,47 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::fs;
+
+mod common;
+
+use common::{TestDir, PASSWORD};
+use stic::{decrypt_file_into, read_header, Compression, Options};
+
+#[test]
+fn round_trips_each_compression() {
+    // Repetitive enough that any of them shrinks it well below its size
+    let data: Vec<u8> = b"compressible line of text\n".repeat(1 << 16);
+
+    for compression in Compression::ALL {
+        let dir = TestDir::new(&format!("compression-{compression}"));
+        let options = Options::new().compression(compression);
+        let encrypted = dir.encrypted(&data, &options);
+
+        assert_eq!(read_header(&encrypted).unwrap().0.compression, compression);
+        let size = fs::metadata(&encrypted).unwrap().len();
+        if compression == Compression::None {
+            assert!(size > data.len() as u64, "{compression}");
+        } else {
+            assert!(size < data.len() as u64 / 10, "{compression}: {size}");
+        }
+
+        let restored =
+            decrypt_file_into(&encrypted, PASSWORD, &dir.0.join("out"), &Options::new()).unwrap();
+        assert_eq!(fs::read(restored).unwrap(), data, "{compression}");
+    }
+}
@@ -0,0 +1,125 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{fs, path::PathBuf};
+
+mod common;
+
+use common::{data, decrypt_error, TestDir, PASSWORD};
+use stic::{read_header, Options, SticError};
+
+/// Size of a sealed segment, 1 MiB of plaintext and its tag.
+const ENCRYPTED_SEGMENT_SIZE: usize = (1 << 20) + 16;
+
+/// Encrypts a file spanning a few segments and returns the encrypted copy and its header size.
+fn encrypted(dir: &TestDir) -> (PathBuf, usize) {
+    let encrypted = dir.encrypted(&data(5 << 19), &Options::new());
+    let header_size = read_header(&encrypted).unwrap().0.size();
+    (encrypted, header_size)
+}
+
+#[test]
+fn reports_wrong_password() {
+    let dir = TestDir::new("errors-password");
+    let (path, _) = encrypted(&dir);
+
+    assert_eq!(
+        decrypt_error(&path, "not the password", &Options::new()),
+        SticError::WrongPassword
+    );
+}
+
+#[test]
+fn reports_unknown_header_fields() {
+    let dir = TestDir::new("errors-header");
+    let (path, _) = encrypted(&dir);
+
+    // The cipher id follows the token
+    let offset = read_header(&path).unwrap().0.token.serialize().len();
+    let mut data = fs::read(&path).unwrap();
+    data[offset] = 0xff;
+    fs::write(&path, data).unwrap();
+
+    assert!(matches!(
+        decrypt_error(&path, PASSWORD, &Options::new()),
+        SticError::CorruptHeader { offset: found, .. } if found == offset as u64
+    ));
+}
+
+#[test]
+fn reports_corrupt_segment() {
+    let dir = TestDir::new("errors-corrupt");
+    let (path, header_size) = encrypted(&dir);
+
+    let second = header_size + ENCRYPTED_SEGMENT_SIZE;
+    let mut data = fs::read(&path).unwrap();
+    data[second + 100] ^= 1;
+    fs::write(&path, data).unwrap();
+
+    assert_eq!(
+        decrypt_error(&path, PASSWORD, &Options::new()),
+        SticError::CorruptPayload {
+            offset: second as u64
+        }
+    );
+}
+
+#[test]
+fn reports_truncation_at_segment_boundary() {
+    let dir = TestDir::new("errors-truncated");
+    let (path, header_size) = encrypted(&dir);
+
+    let end = header_size + 2 * ENCRYPTED_SEGMENT_SIZE;
+    let data = fs::read(&path).unwrap();
+    fs::write(&path, &data[..end]).unwrap();
+
+    assert_eq!(
+        decrypt_error(&path, PASSWORD, &Options::new()),
+        SticError::TruncatedPayload { offset: end as u64 }
+    );
+
+    fs::write(&path, &data[..header_size + 8]).unwrap();
+    assert_eq!(
+        decrypt_error(&path, PASSWORD, &Options::new()),
+        SticError::TruncatedPayload {
+            offset: header_size as u64 + 8
+        }
+    );
+}
+
+#[test]
+fn cli_exits_with_error_status() {
+    let dir = TestDir::new("errors-cli");
+    let (path, header_size) = encrypted(&dir);
+    let decrypt = |path: &PathBuf| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_stic-cli"))
+            .arg("-d")
+            .arg(path)
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+
+    // Both are caught before the password prompt
+    let data = fs::read(&path).unwrap();
+    fs::write(&path, &data[..header_size + 8]).unwrap();
+    assert_eq!(decrypt(&path), Some(4));
+
+    fs::write(&path, [0xff; 512]).unwrap();
+    assert_eq!(decrypt(&path), Some(3));
+}
@@ -0,0 +1,141 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+#![cfg(feature = "ffi")]
+
+use std::{
+    ffi::{CStr, CString},
+    fs, ptr,
+};
+
+mod common;
+
+use common::{TestDir, PASSWORD};
+use stic::ffi::{
+    stic_buffer_free, stic_decrypt_buffer, stic_decrypt_file, stic_encrypt_buffer,
+    stic_encrypt_file, stic_last_error, SticStatus,
+};
+
+fn c_string(string: &str) -> CString {
+    CString::new(string).unwrap()
+}
+
+#[test]
+fn round_trips_buffers() {
+    let password = c_string(PASSWORD);
+    let data = b"stic over the C API";
+    let (mut encrypted, mut encrypted_length) = (ptr::null_mut(), 0);
+    let (mut decrypted, mut decrypted_length) = (ptr::null_mut(), 0);
+
+    unsafe {
+        let status = stic_encrypt_buffer(
+            data.as_ptr(),
+            data.len(),
+            password.as_ptr(),
+            &mut encrypted,
+            &mut encrypted_length,
+        );
+        assert_eq!(status, SticStatus::Ok);
+        assert!(stic_last_error().is_null());
+
+        let status = stic_decrypt_buffer(
+            encrypted,
+            encrypted_length,
+            password.as_ptr(),
+            &mut decrypted,
+            &mut decrypted_length,
+        );
+        assert_eq!(status, SticStatus::Ok);
+        assert_eq!(
+            std::slice::from_raw_parts(decrypted, decrypted_length),
+            data
+        );
+
+        let wrong_password = c_string("Wr0ng password!");
+        let status = stic_decrypt_buffer(
+            encrypted,
+            encrypted_length,
+            wrong_password.as_ptr(),
+            &mut decrypted,
+            &mut decrypted_length,
+        );
+        assert_eq!(status, SticStatus::WrongPassword);
+        assert_eq!(
+            CStr::from_ptr(stic_last_error()).to_str().unwrap(),
+            "Invalid Password"
+        );
+
+        stic_buffer_free(encrypted, encrypted_length);
+        stic_buffer_free(decrypted, decrypted_length);
+    }
+}
+
+#[test]
+fn round_trips_files() {
+    let dir = TestDir::new("ffi");
+    let (input, encrypted) = dir.plaintext(b"stic over the C API");
+
+    let password = c_string(PASSWORD);
+    let input_path = c_string(input.to_str().unwrap());
+    let encrypted_path = c_string(encrypted.to_str().unwrap());
+    let output_dir = c_string(dir.0.join("out").to_str().unwrap());
+
+    unsafe {
+        let status = stic_encrypt_file(
+            input_path.as_ptr(),
+            password.as_ptr(),
+            encrypted_path.as_ptr(),
+        );
+        assert_eq!(status, SticStatus::Ok);
+        let status = stic_decrypt_file(
+            encrypted_path.as_ptr(),
+            password.as_ptr(),
+            output_dir.as_ptr(),
+        );
+        assert_eq!(status, SticStatus::Ok);
+    }
+    assert_eq!(
+        fs::read(dir.0.join("out/file.txt")).unwrap(),
+        b"stic over the C API"
+    );
+}
+
+#[test]
+fn rejects_invalid_arguments() {
+    let status = unsafe { stic_encrypt_file(ptr::null(), ptr::null(), ptr::null()) };
+    assert_eq!(status, SticStatus::InvalidArgument);
+
+    let (root, password) = (c_string("/"), c_string(PASSWORD));
+    let dir = TestDir::new("ffi-root");
+    let output_path = c_string(dir.0.join("root.ic").to_str().unwrap());
+    let status =
+        unsafe { stic_encrypt_file(root.as_ptr(), password.as_ptr(), output_path.as_ptr()) };
+    assert_eq!(status, SticStatus::Error);
+    let message = unsafe { CStr::from_ptr(stic_last_error()) };
+    assert_eq!(message.to_str().unwrap(), "Path has no parent directory");
+}
+
+#[test]
+fn header_is_up_to_date() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/stic.h"));
+    assert_eq!(
+        include_str!("../include/stic.h"),
+        generated,
+        "include/stic.h is stale, copy it from {}",
+        env!("OUT_DIR")
+    );
+}
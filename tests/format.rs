@@ -0,0 +1,111 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::fs;
+
+mod common;
+
+use common::{decrypt_error, TestDir, PASSWORD};
+use stic::{read_header, Header, Options, SticError, Version};
+
+// token (version || KDF id || ...) || algorithm, compression and padding ids || nonce prefix ||
+// key commitment
+const KDF_ID_OFFSET: usize = 4;
+
+fn compression_id_offset(header: &Header) -> usize {
+    header.token.serialize().len() + 1
+}
+
+fn key_commitment_offset(header: &Header) -> usize {
+    header.token.serialize().len() + 3 + header.nonce.len()
+}
+
+#[test]
+fn checks_key_commitment() {
+    let dir = TestDir::new("format-commitment");
+    let path = dir.encrypted(b"header under test", &Options::new());
+
+    let offset = key_commitment_offset(&read_header(&path).unwrap().0);
+    let mut data = fs::read(&path).unwrap();
+    data[offset] ^= 1;
+    fs::write(&path, data).unwrap();
+
+    assert_eq!(
+        decrypt_error(&path, PASSWORD, &Options::new()),
+        SticError::CorruptHeader {
+            offset: offset as u64,
+            reason: String::from("the key commitment does not match the key"),
+        }
+    );
+}
+
+#[test]
+fn refuses_unknown_kdf() {
+    let dir = TestDir::new("format-kdf");
+    let path = dir.encrypted(b"header under test", &Options::new());
+
+    let mut data = fs::read(&path).unwrap();
+    data[KDF_ID_OFFSET] = 0xff;
+    fs::write(&path, data).unwrap();
+
+    assert_eq!(
+        decrypt_error(&path, PASSWORD, &Options::new()),
+        SticError::CorruptHeader {
+            offset: KDF_ID_OFFSET as u64,
+            reason: String::from("Unknown KDF id 255"),
+        }
+    );
+}
+
+#[test]
+fn authenticates_whole_header() {
+    let dir = TestDir::new("format-header");
+    let path = dir.encrypted(b"header under test", &Options::new());
+
+    let offset = compression_id_offset(&read_header(&path).unwrap().0);
+    let mut data = fs::read(&path).unwrap();
+    data[offset] = 3;
+    fs::write(&path, data).unwrap();
+
+    let header_size = read_header(&path).unwrap().0.size() as u64;
+    assert_eq!(
+        decrypt_error(&path, PASSWORD, &Options::new()),
+        SticError::CorruptPayload {
+            offset: header_size
+        }
+    );
+    assert!(fs::read_dir(dir.0.join("out")).unwrap().next().is_none());
+}
+
+#[test]
+fn parses_and_serializes_header() {
+    let dir = TestDir::new("format-parse");
+    let path = dir.encrypted(b"header under test", &Options::new());
+    let data = fs::read(&path).unwrap();
+
+    let header = Header::parse(&data).unwrap();
+    assert_eq!(header.version(), Version::CURRENT);
+    assert_eq!(header.serialize(), data[..header.size()]);
+    assert_eq!(
+        read_header(&path).unwrap(),
+        (header.clone(), data.len() as u64)
+    );
+
+    for size in 0..header.size() {
+        assert!(Header::parse(&data[..size]).is_err(), "parsed {size} bytes");
+    }
+}
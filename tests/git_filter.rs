@@ -0,0 +1,112 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::fs;
+
+mod common;
+
+use common::{TestDir, PASSWORD};
+use stic::{git_clean, git_smudge, read_keyfile, Header, Options};
+
+fn keyfile() -> Vec<u8> {
+    PASSWORD.repeat(4).into_bytes()
+}
+
+fn clean(content: &[u8], deterministic: bool) -> Vec<u8> {
+    let mut encrypted = Vec::new();
+    git_clean(
+        &mut &content[..],
+        &mut encrypted,
+        &keyfile(),
+        deterministic,
+        &Options::new(),
+    )
+    .unwrap();
+    encrypted
+}
+
+fn smudge(stored: &[u8]) -> Vec<u8> {
+    let mut content = Vec::new();
+    git_smudge(&mut &stored[..], &mut content, &keyfile(), &Options::new()).unwrap();
+    content
+}
+
+#[test]
+fn round_trips() {
+    let content = b"api_key = 0xdeadbeef\n";
+    for deterministic in [false, true] {
+        let encrypted = clean(content, deterministic);
+        assert_ne!(encrypted, content);
+        assert_eq!(smudge(&encrypted), content);
+    }
+}
+
+#[test]
+fn skips_password_stretching() {
+    // A random keyfile needs a single HMAC (KDF id 2), not PBKDF2 (1) at a second per blob
+    for deterministic in [false, true] {
+        let encrypted = clean(b"api_key = 0xdeadbeef\n", deterministic);
+        assert_eq!(Header::parse(&encrypted).unwrap().token.kdf, 2);
+    }
+}
+
+#[test]
+fn deterministic_is_stable() {
+    let content = b"api_key = 0xdeadbeef\n";
+    assert_eq!(clean(content, true), clean(content, true));
+    assert_ne!(clean(content, true), clean(b"api_key = 0xfeedface\n", true));
+    assert_ne!(clean(content, false), clean(content, false));
+}
+
+#[test]
+fn passes_plaintext_through() {
+    assert_eq!(
+        smudge(b"committed before the filter\n"),
+        b"committed before the filter\n"
+    );
+    assert_eq!(smudge(b""), b"");
+}
+
+#[test]
+fn refuses_damaged_blobs() {
+    let encrypted = clean(b"api_key = 0xdeadbeef\n", false);
+    let smudge = |stored: &[u8]| {
+        git_smudge(
+            &mut &stored[..],
+            &mut Vec::new(),
+            &keyfile(),
+            &Options::new(),
+        )
+    };
+
+    // Cut inside the header, past it, or with a flipped header byte, the blob is not plaintext
+    assert!(smudge(&encrypted[..100]).is_err());
+    assert!(smudge(&encrypted[..encrypted.len() - 1]).is_err());
+    let mut corrupted = encrypted.clone();
+    corrupted[4] ^= 0xff;
+    assert!(smudge(&corrupted).is_err());
+}
+
+#[test]
+fn rejects_short_keyfiles() {
+    let dir = TestDir::new("git-filter-keyfile");
+    let path = dir.0.join("key");
+    fs::write(&path, b"too short").unwrap();
+    assert!(read_keyfile(&path).is_err());
+    fs::write(&path, keyfile()).unwrap();
+    assert_eq!(*read_keyfile(&path).unwrap(), keyfile());
+}
@@ -0,0 +1,64 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+#![cfg(target_os = "linux")]
+
+use std::{fs, time::Duration};
+
+mod common;
+
+use common::TestDir;
+use stic::{clear_keys, decrypt_file_into, Options, SticError};
+
+/// Moves this thread to a new anonymous session keyring, so the test leaves the one of the
+/// session it runs in alone. False where keyrings are unavailable, as under Docker's default
+/// seccomp profile.
+fn join_new_session_keyring() -> bool {
+    const KEYCTL_JOIN_SESSION_KEYRING: libc::c_long = 1;
+    let id = unsafe {
+        libc::syscall(
+            libc::SYS_keyctl,
+            KEYCTL_JOIN_SESSION_KEYRING,
+            std::ptr::null::<libc::c_char>(),
+        )
+    };
+    id >= 0
+}
+
+#[test]
+fn caches_keys_by_salt() {
+    if !join_new_session_keyring() {
+        eprintln!("skipped: no session keyring");
+        return;
+    }
+    let dir = TestDir::new("keyring");
+    let output = dir.0.join("out");
+    let cached = Options::new().key_cache(Duration::from_secs(60));
+    let encrypted = dir.encrypted(b"cached", &cached);
+
+    // The cached key opens the file whatever the password, but only when asked to use it
+    let wrong = "Wr0ng password!";
+    let error = decrypt_file_into(&encrypted, wrong, &output, &Options::new()).unwrap_err();
+    assert_eq!(error.downcast_ref(), Some(&SticError::WrongPassword));
+    let restored = decrypt_file_into(&encrypted, wrong, &output, &cached).unwrap();
+    assert_eq!(fs::read(&restored).unwrap(), b"cached");
+    fs::remove_file(restored).unwrap();
+
+    clear_keys().unwrap();
+    let error = decrypt_file_into(&encrypted, wrong, &output, &cached).unwrap_err();
+    assert_eq!(error.downcast_ref(), Some(&SticError::WrongPassword));
+}
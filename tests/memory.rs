@@ -0,0 +1,174 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+mod common;
+
+use stic::SecureBuffer;
+
+/// Counts how many page-aligned allocations, which only `SecureBuffer` makes, still held data
+/// when they were freed.
+struct Checking;
+
+static FREED: AtomicUsize = AtomicUsize::new(0);
+static FREED_DIRTY: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Checking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if layout.align() >= 4096 {
+            FREED.fetch_add(1, Ordering::SeqCst);
+            if std::slice::from_raw_parts(ptr, layout.size())
+                .iter()
+                .any(|&byte| byte != 0)
+            {
+                FREED_DIRTY.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Checking = Checking;
+
+#[test]
+fn holds_secrets_like_a_slice() {
+    let mut buffer = SecureBuffer::new(4);
+    assert_eq!(&buffer[..], &[0u8; 4]);
+    buffer.copy_from_slice(b"key!");
+
+    let copy = buffer.clone();
+    buffer.truncate(3);
+    assert_eq!(&buffer[..], b"key");
+    assert_eq!(&copy[..], b"key!");
+    assert_eq!(format!("{copy:?}"), "SecureBuffer(4 bytes)");
+
+    let moved = SecureBuffer::from(b"secret".to_vec());
+    assert_eq!(&moved[..], b"secret");
+    assert!(SecureBuffer::new(0).is_empty());
+}
+
+// Elsewhere buffers are not page-aligned
+#[cfg(unix)]
+#[test]
+fn zeroes_memory_before_freeing_it() {
+    let mut buffer = SecureBuffer::new(3 * 4096);
+    buffer.fill(0xaa);
+    let freed = FREED.load(Ordering::SeqCst);
+    drop(buffer);
+
+    assert!(FREED.load(Ordering::SeqCst) > freed);
+    assert_eq!(FREED_DIRTY.load(Ordering::SeqCst), 0);
+}
+
+/// The `/proc/self/smaps` flags of the mapping holding `address`.
+#[cfg(target_os = "linux")]
+fn mapping_flags(address: usize) -> Vec<String> {
+    let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+    let mut found = false;
+    for line in smaps.lines() {
+        // Each mapping is its address range followed by `Name: value` fields
+        let first = line.split_whitespace().next().unwrap_or_default();
+        if let Some((start, end)) = first.split_once('-') {
+            let start = usize::from_str_radix(start, 16).unwrap();
+            let end = usize::from_str_radix(end, 16).unwrap();
+            found = (start..end).contains(&address);
+        } else if let Some(flags) = line.strip_prefix("VmFlags:").filter(|_| found) {
+            return flags.split_whitespace().map(String::from).collect();
+        }
+    }
+    panic!("{address:#x} is not mapped");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn locks_pages_out_of_swap_and_core_dumps() {
+    let buffer = SecureBuffer::from(&b"secret"[..]);
+    let flags = mapping_flags(buffer.as_ptr() as usize);
+    assert!(
+        flags.iter().any(|flag| flag == "lo"),
+        "not locked: {flags:?}"
+    );
+    assert!(flags.iter().any(|flag| flag == "dd"), "dumped: {flags:?}");
+}
+
+/// Only the owner of a process, or root, can read its memory through `/proc` or ptrace, and
+/// the `/proc/<pid>` files of a process that turned that off belong to root.
+#[cfg(target_os = "linux")]
+#[test]
+fn cli_is_not_dumpable() {
+    use std::{
+        os::unix::{fs::MetadataExt, process::CommandExt},
+        process::{Command, Stdio},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    let dir = common::TestDir::new("memory-dumpable");
+    let keyfile = dir.0.join("key");
+    std::fs::write(&keyfile, [7u8; 32]).unwrap();
+    // Root owns the /proc entries of every process it runs, so the CLI runs as nobody, from a
+    // copy it can reach
+    let root = unsafe { libc::getuid() } == 0;
+    let binary = if root {
+        let binary = dir.0.join("stic-cli");
+        std::fs::copy(env!("CARGO_BIN_EXE_stic-cli"), &binary).unwrap();
+        binary
+    } else {
+        std::path::PathBuf::from(env!("CARGO_BIN_EXE_stic-cli"))
+    };
+    let mut command = Command::new(binary);
+    command
+        .args(["git-filter", "smudge", "--keyfile"])
+        .arg(&keyfile);
+    if root {
+        command.uid(65534).gid(65534);
+    }
+
+    // Waits on stdin for the blob to smudge
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let status = format!("/proc/{}/status", child.id());
+    let start = Instant::now();
+    let owner = loop {
+        let owner = std::fs::metadata(&status).unwrap().uid();
+        if owner == 0 || start.elapsed() > Duration::from_secs(10) {
+            break owner;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    assert!(child.try_wait().unwrap().is_none(), "exited early");
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert_eq!(owner, 0);
+}
@@ -0,0 +1,222 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    fs::{self, Permissions},
+    os::unix::fs::{chown, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+mod common;
+
+use common::{TestDir, PASSWORD};
+use filetime::FileTime;
+use stic::{decrypt_file_into, encrypt_file_with, Options, Preserve};
+
+const XATTR: &str = "user.stic.test";
+
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Builds `root/data` with a file, an executable and a read-only subdirectory, all with odd
+/// modes and times, and returns whether the filesystem accepted the xattr.
+fn populate(root: &Path) -> bool {
+    let data = root.join("data");
+    fs::create_dir_all(data.join("sub")).unwrap();
+    fs::write(data.join("file.txt"), b"metadata round-trip").unwrap();
+    fs::write(data.join("run.sh"), b"#!/bin/sh\n").unwrap();
+    fs::write(data.join("sub/inner"), b"inner").unwrap();
+
+    fs::set_permissions(data.join("file.txt"), Permissions::from_mode(0o640)).unwrap();
+    fs::set_permissions(data.join("run.sh"), Permissions::from_mode(0o4750)).unwrap();
+    fs::set_permissions(data.join("sub/inner"), Permissions::from_mode(0o600)).unwrap();
+    fs::set_permissions(data.join("sub"), Permissions::from_mode(0o550)).unwrap();
+
+    if is_root() {
+        chown(data.join("file.txt"), Some(1234), Some(4321)).unwrap();
+    }
+
+    let xattr = xattr::set(data.join("file.txt"), XATTR, b"label").is_ok();
+
+    for (index, path) in PATHS.iter().enumerate() {
+        let (atime, mtime) = times(index);
+        filetime::set_file_times(data.join(path), atime, mtime).unwrap();
+    }
+    xattr
+}
+
+const PATHS: [&str; 5] = ["file.txt", "run.sh", "sub/inner", "sub", ""];
+
+/// Times given to `PATHS[index]`. Reading the originals during encryption may update their
+/// access times, so the restored files are compared against these instead.
+fn times(index: usize) -> (FileTime, FileTime) {
+    (
+        FileTime::from_unix_time(1_000_000_000 + index as i64, 123_456_789),
+        FileTime::from_unix_time(1_500_000_000 + index as i64, 987_654_321),
+    )
+}
+
+fn round_trip(root: &Path, preserve: Preserve) -> PathBuf {
+    let options = Options::new().preserve(preserve);
+    let encrypted = root.join("data.ic");
+    encrypt_file_with(&root.join("data"), PASSWORD, &encrypted, &options).unwrap();
+    decrypt_file_into(&encrypted, PASSWORD, &root.join("out"), &options).unwrap()
+}
+
+#[test]
+fn preserves_all_metadata() {
+    let dir = TestDir::new("preserve");
+    let xattr = populate(&dir.0);
+    let restored = round_trip(&dir.0, Preserve::ALL);
+
+    for (index, path) in PATHS.iter().enumerate() {
+        let original = fs::metadata(dir.0.join("data").join(path)).unwrap();
+        let copy = fs::metadata(restored.join(path)).unwrap();
+        let (atime, mtime) = times(index);
+
+        assert_eq!(original.mode(), copy.mode(), "mode of {path:?}");
+        assert_eq!(
+            FileTime::from_last_modification_time(&copy),
+            mtime,
+            "mtime of {path:?}"
+        );
+        assert_eq!(
+            FileTime::from_last_access_time(&copy),
+            atime,
+            "atime of {path:?}"
+        );
+        if is_root() {
+            assert_eq!(
+                (original.uid(), original.gid()),
+                (copy.uid(), copy.gid()),
+                "ownership of {path:?}"
+            );
+        }
+    }
+
+    if xattr {
+        let value = xattr::get(restored.join("file.txt"), XATTR).unwrap();
+        assert_eq!(value.as_deref(), Some(&b"label"[..]));
+    }
+    fs::set_permissions(restored.join("sub"), Permissions::from_mode(0o755)).unwrap();
+    fs::set_permissions(dir.0.join("data/sub"), Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn drops_disabled_metadata() {
+    let dir = TestDir::new("no-preserve");
+    populate(&dir.0);
+    let restored = round_trip(&dir.0, Preserve::NONE);
+
+    let file = fs::metadata(restored.join("file.txt")).unwrap();
+    assert_eq!(file.mode() & 0o7777, 0o644);
+    let script = fs::metadata(restored.join("run.sh")).unwrap();
+    assert_eq!(script.mode() & 0o7777, 0o755);
+    assert_ne!(FileTime::from_last_access_time(&file), times(0).0);
+    assert_eq!(xattr::get(restored.join("file.txt"), XATTR).unwrap(), None);
+    if is_root() {
+        assert_eq!((file.uid(), file.gid()), (0, 0));
+    }
+    fs::set_permissions(dir.0.join("data/sub"), Permissions::from_mode(0o755)).unwrap();
+}
+
+/// Drops `CAP_SYS_ADMIN`, which setting `trusted.*` attributes takes, from the calling thread
+/// and the threads it starts, leaving the rest of the test binary alone.
+fn drop_sys_admin() -> bool {
+    #[repr(C)]
+    struct Header {
+        version: u32,
+        pid: i32,
+    }
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct Sets {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+    const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+    const CAP_SYS_ADMIN: u32 = 21;
+
+    let mut header = Header {
+        version: CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut sets = [Sets::default(); 2];
+    unsafe {
+        if libc::syscall(libc::SYS_capget, &mut header, sets.as_mut_ptr()) != 0 {
+            return false;
+        }
+        sets[0].effective &= !(1 << CAP_SYS_ADMIN);
+        libc::syscall(libc::SYS_capset, &mut header, sets.as_ptr()) == 0
+    }
+}
+
+#[test]
+fn skips_xattrs_it_cannot_set() {
+    let dir = TestDir::new("xattr-refused");
+    let data = dir.0.join("data");
+    fs::create_dir(&data).unwrap();
+    fs::write(data.join("file.txt"), b"labelled").unwrap();
+    // Only root can record a trusted.* attribute in the first place
+    if !is_root()
+        || xattr::set(data.join("file.txt"), "trusted.stic.test", b"host").is_err()
+        || xattr::set(data.join("file.txt"), XATTR, b"label").is_err()
+    {
+        eprintln!("skipping: trusted.* and user.* attributes can't be set here");
+        return;
+    }
+    let encrypted = dir.0.join("data.ic");
+    encrypt_file_with(&data, PASSWORD, &encrypted, &Options::new()).unwrap();
+
+    // Like decrypting as another user than the one who encrypted
+    assert!(drop_sys_admin());
+    let probe = xattr::set(data.join("file.txt"), "trusted.stic.probe", b"");
+    assert_eq!(probe.unwrap_err().raw_os_error(), Some(libc::EPERM));
+
+    let restored = decrypt_file_into(&encrypted, PASSWORD, &dir.0.join("out"), &Options::new());
+    let restored = restored.unwrap().join("file.txt");
+    assert_eq!(fs::read(&restored).unwrap(), b"labelled");
+    let value = xattr::get(&restored, XATTR).unwrap();
+    assert_eq!(value.as_deref(), Some(&b"label"[..]));
+}
+
+#[test]
+fn preserves_times_before_1970() {
+    let dir = TestDir::new("pre-epoch");
+    let data = dir.0.join("data");
+    fs::create_dir(&data).unwrap();
+    // Half a second and a quarter past 1234 seconds before the epoch, a whole second before it
+    let times = [
+        FileTime::from_unix_time(-1, 500_000_000),
+        FileTime::from_unix_time(-1235, 750_000_000),
+        FileTime::from_unix_time(-1, 0),
+    ];
+    for (index, time) in times.iter().enumerate() {
+        let path = data.join(index.to_string());
+        fs::write(&path, b"old").unwrap();
+        filetime::set_file_times(&path, *time, *time).unwrap();
+    }
+
+    let restored = round_trip(&dir.0, Preserve::ALL);
+    for (index, time) in times.iter().enumerate() {
+        let copy = fs::metadata(restored.join(index.to_string())).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&copy), *time);
+        assert_eq!(FileTime::from_last_access_time(&copy), *time);
+    }
+}
@@ -0,0 +1,82 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{fs, path::Path};
+
+mod common;
+
+use common::{data, TestDir, PASSWORD};
+use stic::{decrypt_file_into, read_header, Options, Padding};
+
+const SEGMENT_SIZE: u64 = 1 << 20;
+const TAG_SIZE: u64 = 16;
+const BUCKET_SIZE: u64 = 1 << 20;
+
+/// Size of the padded payload in `path`, that of its segments without their tags.
+fn payload_size(path: &Path) -> u64 {
+    let (header, size) = read_header(path).unwrap();
+    let encrypted = size - header.size() as u64;
+    encrypted - encrypted.div_ceil(SEGMENT_SIZE + TAG_SIZE) * TAG_SIZE
+}
+
+/// Encrypts `data` with `padding`, checks that it decrypts to exactly `data` and returns the
+/// padded payload size.
+fn round_trip(name: &str, data: &[u8], padding: Padding) -> u64 {
+    let dir = TestDir::new(&format!("padding-{name}"));
+    let encrypted = dir.encrypted(data, &Options::new().padding(padding));
+    assert_eq!(read_header(&encrypted).unwrap().0.padding, padding);
+
+    let restored =
+        decrypt_file_into(&encrypted, PASSWORD, &dir.0.join("out"), &Options::new()).unwrap();
+    assert_eq!(fs::read(restored).unwrap(), data, "{name}");
+    payload_size(&encrypted)
+}
+
+#[test]
+fn pads_to_buckets() {
+    let sizes = [1, 1_000, 300_000, 1_500_000];
+    let padded =
+        sizes.map(|size| round_trip(&format!("bucket-{size}"), &data(size), Padding::Bucket));
+
+    assert_eq!(
+        padded,
+        [BUCKET_SIZE, BUCKET_SIZE, BUCKET_SIZE, 2 * BUCKET_SIZE]
+    );
+}
+
+#[test]
+fn pads_to_padme_sizes() {
+    for size in [1_000, 100_000, 3_000_000] {
+        let padded = round_trip(&format!("padme-{size}"), &data(size), Padding::Padme);
+
+        // PADMÉ only keeps the top log2(log2(n)) + 1 bits below the leading one
+        let exponent = padded.ilog2();
+        let kept = exponent.ilog2() + 1;
+        assert_eq!(padded % (1 << (exponent - kept)), 0, "{size}: {padded}");
+        // At most 12% over the content, which the tar headers add a few KiB to
+        assert!(
+            padded >= size as u64 && padded < size as u64 * 112 / 100 + 4096,
+            "{size}: {padded}"
+        );
+    }
+
+    // Sizes this close can't be told apart
+    assert_eq!(
+        round_trip("padme-close", &data(100_000), Padding::Padme),
+        round_trip("padme-closer", &data(100_100), Padding::Padme)
+    );
+}
@@ -0,0 +1,80 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+};
+
+mod common;
+
+use common::{data, TestDir, PASSWORD};
+use stic::{decrypt_file_into, encrypt_file_with, Options, Phase, Progress};
+
+/// Every update it gets, in order.
+#[derive(Default)]
+struct Recorder(Mutex<Vec<(Phase, u64, u64)>>);
+
+impl Progress for Recorder {
+    fn update(&self, phase: Phase, done: u64, total: u64) {
+        self.0.lock().unwrap().push((phase, done, total));
+    }
+}
+
+/// Checks that `phases` each report a fixed total, with `done` never going back and ending at it.
+fn assert_complete(recorder: &Recorder, phases: &[Phase]) {
+    let updates = recorder.0.lock().unwrap();
+    for &phase in phases {
+        let updates: Vec<_> = updates
+            .iter()
+            .filter(|update| update.0 == phase)
+            .map(|&(_, done, total)| (done, total))
+            .collect();
+        assert!(updates.len() > 1, "{phase}: {updates:?}");
+
+        let total = updates[0].1;
+        assert!(updates.iter().all(|update| update.1 == total), "{phase}");
+        assert!(
+            updates.windows(2).all(|pair| pair[0].0 <= pair[1].0),
+            "{phase}"
+        );
+        assert_eq!(updates.last().unwrap().0, total, "{phase}");
+    }
+}
+
+#[test]
+fn reports_every_phase_to_its_end() {
+    let dir = TestDir::new("progress");
+    let input = dir.0.join("input");
+    fs::create_dir(&input).unwrap();
+    // Several segments spread over a few files, so every phase reports more than once
+    for i in 0..3 {
+        fs::write(input.join(format!("file{i}.txt")), data(3 << 19)).unwrap();
+    }
+    let encrypted = dir.0.join("input.ic");
+
+    let recorder = Arc::new(Recorder::default());
+    let options = Options::new().progress(recorder.clone());
+    encrypt_file_with(&input, PASSWORD, &encrypted, &options).unwrap();
+    assert_complete(&recorder, &[Phase::Tar, Phase::Kdf, Phase::Encrypt]);
+    assert_eq!(recorder.0.lock().unwrap()[0].2, 9 << 19);
+
+    let recorder = Arc::new(Recorder::default());
+    let options = Options::new().progress(recorder.clone());
+    decrypt_file_into(&encrypted, PASSWORD, &dir.0.join("out"), &options).unwrap();
+    assert_complete(&recorder, &[Phase::Kdf, Phase::Decrypt, Phase::Extract]);
+}
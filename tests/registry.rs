@@ -0,0 +1,146 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    error::Error,
+    fs,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+mod common;
+
+use chacha20poly1305::{
+    aead::{Aead as _, KeyInit, Payload},
+    XChaCha20Poly1305,
+};
+use common::{TestDir, PASSWORD};
+use stic::{decrypt_file_into, Aead, Algorithm, Options, PasswordKdf, Registry, SticError};
+
+/// XChaCha20-Poly1305 from outside the crate, counting the segments it handles.
+#[derive(Default)]
+struct CountingAead(AtomicUsize);
+
+impl Aead for CountingAead {
+    fn id(&self) -> u8 {
+        Algorithm::XChaCha20Poly1305.id()
+    }
+
+    fn seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        Ok(XChaCha20Poly1305::new_from_slice(key)?.encrypt(nonce.into(), payload)?)
+    }
+
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        sealed: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        let payload = Payload { msg: sealed, aad };
+        Ok(XChaCha20Poly1305::new_from_slice(key)?.decrypt(nonce.into(), payload)?)
+    }
+}
+
+/// A deliberately weak KDF, only distinguishable from the built-in one by its output.
+struct XorKdf;
+
+impl PasswordKdf for XorKdf {
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn derive(&self, password: &[u8], salt: &[u8], key: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        for (index, byte) in key.iter_mut().enumerate() {
+            *byte = password[index % password.len()] ^ salt[index % salt.len()];
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn uses_registered_aead() {
+    let dir = TestDir::new("registry-aead");
+    let aead = Arc::new(CountingAead::default());
+    let mut registry = Registry::new();
+    registry.register_aead(aead.clone()).unwrap();
+
+    let options = Options::new()
+        .algorithm(Algorithm::XChaCha20Poly1305)
+        .registry(registry);
+    let encrypted = dir.encrypted(b"registered implementations", &options);
+    assert_eq!(aead.0.load(Ordering::Relaxed), 1);
+
+    // Same bytes as the built-in implementation
+    let restored = decrypt_file_into(&encrypted, PASSWORD, &dir.0.join("out"), &Options::new());
+    assert_eq!(
+        fs::read(restored.unwrap()).unwrap(),
+        b"registered implementations"
+    );
+}
+
+#[test]
+fn uses_registered_kdf() {
+    let dir = TestDir::new("registry-kdf");
+    let mut registry = Registry::new();
+    registry.register_kdf(Arc::new(XorKdf)).unwrap();
+    let options = Options::new().registry(registry);
+    let encrypted = dir.encrypted(b"registered implementations", &options);
+
+    let error =
+        decrypt_file_into(&encrypted, PASSWORD, &dir.0.join("out"), &Options::new()).unwrap_err();
+    assert_eq!(error.downcast_ref(), Some(&SticError::WrongPassword));
+
+    let restored = decrypt_file_into(&encrypted, PASSWORD, &dir.0.join("out"), &options);
+    assert_eq!(
+        fs::read(restored.unwrap()).unwrap(),
+        b"registered implementations"
+    );
+}
+
+#[test]
+fn refuses_unknown_ids() {
+    struct Unknown;
+
+    impl PasswordKdf for Unknown {
+        fn id(&self) -> u8 {
+            0xff
+        }
+
+        fn derive(&self, _: &[u8], _: &[u8], _: &mut [u8]) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+    }
+
+    let mut registry = Registry::new();
+    let error = registry.register_kdf(Arc::new(Unknown)).unwrap_err();
+    assert_eq!(error.to_string(), "Unknown KDF id 255");
+}
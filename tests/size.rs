@@ -0,0 +1,132 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    error::Error,
+    fs::{self, File},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+mod common;
+
+use chacha20poly1305::{
+    aead::{Aead as _, KeyInit, Payload},
+    XChaCha20Poly1305,
+};
+use common::{data, TestDir};
+use stic::{
+    read_header, validate_decryption, validate_encryption, Aead, Algorithm, Options, Registry,
+};
+
+const V1_MAX_FILE_SIZE: u64 = 68_719_476_704;
+
+fn fixtures() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"))
+}
+
+/// Grows `path` to `size` without writing the data, so the test doesn't need that much disk.
+fn grow(path: &Path, size: u64) {
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_len(size)
+        .unwrap();
+}
+
+#[test]
+fn accepts_files_over_64_gib() {
+    let dir = TestDir::new("size-validate");
+    let size = V1_MAX_FILE_SIZE + (1 << 30);
+
+    let (input, _) = dir.plaintext(b"sparse");
+    grow(&input, size);
+    validate_encryption(&input).unwrap();
+
+    let encrypted = dir.encrypted(b"sparse", &Options::new());
+    grow(&encrypted, size);
+    validate_decryption(&encrypted).unwrap();
+
+    // Which the single GCM invocation of the first version can't have encrypted
+    let v1 = dir.0.join("v1.ic");
+    fs::copy(fixtures().join("v1.ic"), &v1).unwrap();
+    validate_decryption(&v1).unwrap();
+    grow(&v1, size);
+    assert!(validate_decryption(&v1).is_err());
+}
+
+/// XChaCha20-Poly1305 that records the nonce of each segment it seals.
+#[derive(Default)]
+struct Recording(Mutex<Vec<Vec<u8>>>);
+
+impl Aead for Recording {
+    fn id(&self) -> u8 {
+        Algorithm::XChaCha20Poly1305.id()
+    }
+
+    fn seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        self.0.lock().unwrap().push(nonce.to_vec());
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        Ok(XChaCha20Poly1305::new_from_slice(key)?.encrypt(nonce.into(), payload)?)
+    }
+
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        sealed: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let payload = Payload { msg: sealed, aad };
+        Ok(XChaCha20Poly1305::new_from_slice(key)?.decrypt(nonce.into(), payload)?)
+    }
+}
+
+#[test]
+fn counts_segments_in_64_bits() {
+    let dir = TestDir::new("size-nonces");
+    let recording = Arc::new(Recording::default());
+    let mut registry = Registry::new();
+    registry.register_aead(recording.clone()).unwrap();
+    let options = Options::new()
+        .algorithm(Algorithm::XChaCha20Poly1305)
+        .registry(registry);
+
+    let encrypted = dir.encrypted(&data((2 << 20) + 1), &options);
+    let prefix = read_header(&encrypted).unwrap().0.nonce;
+
+    let mut nonces = recording.0.lock().unwrap().clone();
+    nonces.sort();
+    assert_eq!(nonces.len(), 3);
+    for (index, nonce) in nonces.iter().enumerate() {
+        // prefix || segment index (u64, big endian) || last segment flag
+        let mut expected = prefix.clone();
+        expected.extend_from_slice(&(index as u64).to_be_bytes());
+        expected.push((index == 2) as u8);
+        assert_eq!(nonce, &expected);
+    }
+}
@@ -0,0 +1,172 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+//! The multi-threaded segment pipeline behind `encrypt_file` and `decrypt_file`.
+
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+mod common;
+
+use chacha20poly1305::{
+    aead::{Aead as _, KeyInit, Payload},
+    XChaCha20Poly1305,
+};
+use common::{data, decrypt_error, TestDir, PASSWORD};
+use stic::{
+    decrypt_file_into, encrypt_file_with, Aead, Algorithm, Header, Options, Registry, SticError,
+};
+
+const SEGMENT_SIZE: usize = 1 << 20;
+const ENCRYPTED_SEGMENT_SIZE: usize = SEGMENT_SIZE + 16;
+
+/// Six segments, the last one short.
+const SIZE: usize = 5 * SEGMENT_SIZE + 12_345;
+
+/// XChaCha20-Poly1305 that calls `hook` with the segment index before each seal or open.
+struct Hooked<F>(F);
+
+fn segment_index(nonce: &[u8]) -> u64 {
+    // Nonces end with the segment index and the last segment flag
+    u64::from_be_bytes(nonce[nonce.len() - 9..nonce.len() - 1].try_into().unwrap())
+}
+
+impl<F> Aead for Hooked<F>
+where
+    F: Fn(u64) -> Result<(), Box<dyn Error + Send + Sync>> + Send + Sync,
+{
+    fn id(&self) -> u8 {
+        Algorithm::XChaCha20Poly1305.id()
+    }
+
+    fn seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        (self.0)(segment_index(nonce))?;
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        Ok(XChaCha20Poly1305::new_from_slice(key)?.encrypt(nonce.into(), payload)?)
+    }
+
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        sealed: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        (self.0)(segment_index(nonce))?;
+        let payload = Payload { msg: sealed, aad };
+        Ok(XChaCha20Poly1305::new_from_slice(key)?.decrypt(nonce.into(), payload)?)
+    }
+}
+
+fn hooked<F>(hook: F, threads: usize) -> Options
+where
+    F: Fn(u64) -> Result<(), Box<dyn Error + Send + Sync>> + Send + Sync + 'static,
+{
+    let mut registry = Registry::new();
+    registry.register_aead(Arc::new(Hooked(hook))).unwrap();
+    Options::new()
+        .algorithm(Algorithm::XChaCha20Poly1305)
+        .registry(registry)
+        .threads(threads)
+}
+
+fn decrypt(encrypted: &Path, options: &Options) -> Vec<u8> {
+    let output = encrypted.parent().unwrap().join("out");
+    let restored = decrypt_file_into(encrypted, PASSWORD, &output, options).unwrap();
+    let data = fs::read(&restored).unwrap();
+    fs::remove_file(restored).unwrap();
+    data
+}
+
+/// Runs `f` on another thread, failing instead of hanging if the pipeline deadlocks.
+fn within_a_minute<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || sender.send(f()));
+    receiver
+        .recv_timeout(Duration::from_secs(60))
+        .expect("pipeline deadlocked")
+}
+
+#[test]
+fn decrypts_with_any_thread_count() {
+    let dir = TestDir::new("stream-threads");
+    for (encrypting, decrypting) in [(1, 4), (4, 1)] {
+        let encrypted = dir.encrypted(&data(SIZE), &Options::new().threads(encrypting));
+        let decrypted = decrypt(&encrypted, &Options::new().threads(decrypting));
+        assert!(
+            decrypted == data(SIZE),
+            "{encrypting} then {decrypting} threads"
+        );
+    }
+}
+
+#[test]
+fn writes_segments_in_order() {
+    // Later segments finish first, so results arrive in reverse order
+    let reversed = |index: u64| {
+        thread::sleep(Duration::from_millis(40 * (6 - index.min(6))));
+        Ok(())
+    };
+    let dir = TestDir::new("stream-order");
+    let encrypted = dir.encrypted(&data(SIZE), &hooked(reversed, 4));
+    assert!(decrypt(&encrypted, &Options::new().threads(1)) == data(SIZE));
+    assert!(decrypt(&encrypted, &hooked(reversed, 4)) == data(SIZE));
+}
+
+#[test]
+fn stops_at_a_failing_segment() {
+    let failing = |index: u64| match index {
+        2 => Err(Box::from("segment 2 failed")),
+        _ => Ok(()),
+    };
+    let dir = TestDir::new("stream-failure");
+    let (input, encrypted) = dir.plaintext(&data(SIZE));
+    let error = within_a_minute(move || {
+        let options = hooked(failing, 4);
+        encrypt_file_with(&input, PASSWORD, &encrypted, &options).map_err(|e| e.to_string())
+    });
+    assert_eq!(error.unwrap_err(), "segment 2 failed");
+    let mut remaining: Vec<_> = fs::read_dir(&dir.0)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    remaining.sort();
+    assert_eq!(remaining, ["file.txt", "out"]);
+
+    let encrypted = dir.encrypted(&data(SIZE), &hooked(|_| Ok(()), 4));
+    let header_size = Header::parse(&fs::read(&encrypted).unwrap())
+        .unwrap()
+        .size();
+    let error = within_a_minute(move || decrypt_error(&encrypted, PASSWORD, &hooked(failing, 4)));
+    let offset = (header_size + 2 * ENCRYPTED_SEGMENT_SIZE) as u64;
+    assert_eq!(error, SticError::CorruptPayload { offset });
+}
@@ -0,0 +1,119 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+use std::{
+    fs,
+    os::unix::{fs::symlink, net::UnixListener},
+    path::{Path, PathBuf},
+};
+
+mod common;
+
+use common::{TestDir, PASSWORD};
+use stic::{decrypt_file_into, encrypt_file_with, Options, Symlinks};
+
+fn encrypt(root: &Path, symlinks: Symlinks) -> Result<PathBuf, String> {
+    let options = Options::new().symlinks(symlinks);
+    let encrypted = root.join("data.ic");
+    encrypt_file_with(&root.join("data"), PASSWORD, &encrypted, &options)
+        .map_err(|e| e.to_string())?;
+    Ok(decrypt_file_into(&encrypted, PASSWORD, &root.join("out"), &options).unwrap())
+}
+
+/// `root/data` with a file and a relative link to it.
+fn populate(root: &Path) -> PathBuf {
+    let data = root.join("data");
+    fs::create_dir_all(&data).unwrap();
+    fs::write(data.join("file"), b"target").unwrap();
+    symlink("file", data.join("link")).unwrap();
+    data
+}
+
+#[test]
+fn preserves_symlinks() {
+    let dir = TestDir::new("symlinks-preserve");
+    populate(&dir.0);
+    let restored = encrypt(&dir.0, Symlinks::Preserve).unwrap();
+
+    let link = restored.join("link");
+    assert!(link.symlink_metadata().unwrap().is_symlink());
+    assert_eq!(fs::read_link(&link).unwrap(), Path::new("file"));
+    assert_eq!(fs::read(link).unwrap(), b"target");
+}
+
+#[test]
+fn follows_symlinks() {
+    let dir = TestDir::new("symlinks-follow");
+    populate(&dir.0);
+    let restored = encrypt(&dir.0, Symlinks::Follow).unwrap();
+
+    let link = restored.join("link");
+    assert!(link.symlink_metadata().unwrap().is_file());
+    assert_eq!(fs::read(link).unwrap(), b"target");
+}
+
+#[test]
+fn follows_symlink_given_as_path() {
+    let dir = TestDir::new("symlinks-root");
+    let data = populate(&dir.0);
+    fs::rename(&data, dir.0.join("target")).unwrap();
+    symlink("target", &data).unwrap();
+
+    // Archiving the link itself would leave nothing once the target is gone
+    let restored = encrypt(&dir.0, Symlinks::Preserve).unwrap();
+    assert_eq!(restored, dir.0.join("out/data"));
+    assert!(restored.symlink_metadata().unwrap().is_dir());
+    assert_eq!(fs::read(restored.join("file")).unwrap(), b"target");
+    assert!(restored
+        .join("link")
+        .symlink_metadata()
+        .unwrap()
+        .is_symlink());
+}
+
+#[test]
+fn refuses_broken_symlinks_when_following() {
+    let dir = TestDir::new("symlinks-broken");
+    let data = populate(&dir.0);
+    symlink("missing", data.join("broken")).unwrap();
+
+    let error = encrypt(&dir.0, Symlinks::Follow).unwrap_err();
+    assert!(error.ends_with("broken is a broken symlink"), "{error}");
+    assert!(encrypt(&dir.0, Symlinks::Preserve).is_ok());
+}
+
+#[test]
+fn refuses_symlink_loops() {
+    let dir = TestDir::new("symlinks-loop");
+    let data = populate(&dir.0);
+    symlink(".", data.join("itself")).unwrap();
+
+    let error = encrypt(&dir.0, Symlinks::Follow).unwrap_err();
+    assert!(error.ends_with("data/itself is a symlink loop"), "{error}");
+    encrypt(&dir.0, Symlinks::Preserve).unwrap();
+}
+
+#[test]
+fn refuses_sockets() {
+    let dir = TestDir::new("symlinks-socket");
+    let data = populate(&dir.0);
+    let _listener = UnixListener::bind(data.join("socket")).unwrap();
+
+    let error = encrypt(&dir.0, Symlinks::Preserve).unwrap_err();
+    assert!(error.contains("socket is a socket"), "{error}");
+    assert!(!dir.0.join("data.ic").exists());
+}
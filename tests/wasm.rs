@@ -0,0 +1,62 @@
+/*
+This file is part of stic.
+
+stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+General Public License as published by the Free Software Foundation, either version 3 of the
+License, or (at your option) any later version.
+
+stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+Public License for more details.
+
+You should have received a copy of the GNU General Public License along with stic.
+If not, see <https://www.gnu.org/licenses/>.
+
+Copyright (C) 2022 Salvador Bravo Garnica
+*/
+
+//! The `wasm` bindings on the native target: only their successful paths, which never reach
+//! into JavaScript. `tests/wasm/test.mjs` covers the built package under Node.
+
+#![cfg(feature = "wasm")]
+
+use std::fs;
+
+mod common;
+
+use common::{TestDir, PASSWORD};
+use stic::{decrypt_file_into, wasm, Options};
+
+#[test]
+fn reads_native_files() {
+    for version in ["v2", "v3", "v4"] {
+        let data = fs::read(format!("tests/fixtures/{version}.ic")).unwrap();
+
+        let entries = wasm::list(&data, PASSWORD).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            (
+                entries[0].path.as_str(),
+                entries[0].size,
+                entries[0].kind.as_str()
+            ),
+            ("fixture.txt", 13.0, "file")
+        );
+
+        let content = wasm::extract(&data, PASSWORD, "fixture.txt").unwrap();
+        assert_eq!(content, fs::read("tests/fixtures/fixture.txt").unwrap());
+    }
+}
+
+#[test]
+fn writes_native_files() {
+    let dir = TestDir::new("wasm");
+    let encrypted = dir.0.join("note.txt.ic");
+    let data = wasm::encrypt("note.txt", b"from the browser", PASSWORD, 1.7e12).unwrap();
+    fs::write(&encrypted, data).unwrap();
+
+    let restored =
+        decrypt_file_into(&encrypted, PASSWORD, &dir.0.join("out"), &Options::new()).unwrap();
+    assert_eq!(restored, dir.0.join("out/note.txt"));
+    assert_eq!(fs::read(restored).unwrap(), b"from the browser");
+}
@@ -0,0 +1,63 @@
+// This file is part of stic.
+
+// stic is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+
+// stic is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+// the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+
+// You should have received a copy of the GNU General Public License along with stic.
+// If not, see <https://www.gnu.org/licenses/>.
+
+// Copyright (C) 2022 Salvador Bravo Garnica
+
+// Runs the `wasm` bindings under Node against the fixtures written by the native build:
+//
+//     cargo rustc --release --lib --crate-type cdylib --target wasm32-unknown-unknown \
+//         --no-default-features --features wasm
+//     wasm-bindgen --target nodejs --out-dir pkg target/wasm32-unknown-unknown/release/stic.wasm
+//     node --test tests/wasm/
+//
+// as .github/workflows/wasm.yml does. zstd and xz are C libraries and are left out of the wasm32
+// build, which only reads and writes files without compression or with gzip.
+
+import assert from "node:assert/strict";
+import { readFileSync } from "node:fs";
+import { test } from "node:test";
+
+import * as stic from "../../pkg/stic.js";
+
+const PASSWORD = "Passw0rd!";
+const fixture = (name) => readFileSync(new URL(`../fixtures/${name}`, import.meta.url));
+
+test("reads files encrypted by the CLI", () => {
+  for (const version of ["v2", "v3", "v4"]) {
+    const data = fixture(`${version}.ic`);
+    const [entry, ...rest] = stic.list(data, PASSWORD);
+    assert.equal(rest.length, 0);
+    assert.deepEqual([entry.path, entry.size, entry.kind], ["fixture.txt", 13, "file"]);
+    assert.deepEqual(
+      Buffer.from(stic.extract(data, PASSWORD, "fixture.txt")),
+      fixture("fixture.txt"),
+    );
+  }
+});
+
+test("round-trips its own files", () => {
+  const content = new TextEncoder().encode("from the browser");
+  const encrypted = stic.encrypt("note.txt", content, PASSWORD, Date.now());
+  assert.deepEqual(stic.extract(encrypted, PASSWORD, "note.txt"), content);
+});
+
+test("names errors after their kind", () => {
+  assert.throws(() => stic.list(fixture("v3.ic"), "Wr0ng password!"), {
+    name: "WrongPassword",
+  });
+
+  const truncated = fixture("v3.ic").subarray(0, -1);
+  assert.throws(() => stic.list(truncated, PASSWORD), (e) => {
+    return e.name === "CorruptPayload" && typeof e.offset === "number";
+  });
+});